@@ -1,24 +1,49 @@
-wit_bindgen_rust::export!("../loader.wit");
+wit_bindgen_rust::export!("../loader-v2.wit");
 
-struct Loader;
+use loader_v2::{Compilation, Diagnostic, Metadata, Output, Severity, TransformOptions};
 
-impl loader::Loader for Loader {
-    fn transform(source: String) -> loader::Compilation {
-        match compile("module.ts", source) {
-            Ok(ret) => loader::Compilation::Success(ret),
-            Err(err) => loader::Compilation::Failure(err.to_string()),
+struct LoaderV2;
+
+impl loader_v2::LoaderV2 for LoaderV2 {
+    fn metadata() -> Metadata {
+        Metadata {
+            version: 2,
+            extensions: vec![String::from("ts"), String::from("tsx")],
+            resolver: false,
         }
     }
 
-    fn extension() -> Vec<String> {
-        vec![String::from("ts"), String::from("tsx")]
+    fn transform(source: String, options: TransformOptions) -> Compilation {
+        let config = if options.config.is_empty() {
+            Config::default()
+        } else {
+            match serde_json::from_str(&options.config) {
+                Ok(config) => config,
+                Err(err) => {
+                    return Compilation::Failure(vec![Diagnostic {
+                        severity: Severity::Error,
+                        message: format!("invalid typescript loader config: {}", err),
+                        file: options.path,
+                        line: 0,
+                        column: 0,
+                    }])
+                }
+            }
+        };
+
+        compile(&options.path, source, &config)
+    }
+
+    fn resolve(_specifier: String, _base: String) -> Option<String> {
+        None
     }
 }
 
-use std::sync::Arc;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
 use swc::{
     common::{
-        errors::{ColorConfig, Handler},
+        errors::{DiagnosticBuilder, Emitter, Handler, Level},
         FileName, SourceMap,
     },
     config::{JscConfig, Options},
@@ -26,16 +51,73 @@ use swc::{
     // sourcemap::SourceMap,
 };
 
-fn compile(name: &str, source: impl ToString) -> Result<String, anyhow::Error> {
+/// Configuration read from `typescript.toml` and the `[loaders.typescript]`
+/// table of the project `scriptor.toml`
+#[derive(Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct Config {
+    target: EsVersion,
+    /// Parse `.ts` files as tsx too. `.tsx` files always allow jsx
+    tsx: bool,
+    decorators: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            target: EsVersion::Es2017,
+            tsx: false,
+            decorators: false,
+        }
+    }
+}
+
+/// Collects swc diagnostics so they can be handed back to the host
+struct Collector {
+    cm: Arc<SourceMap>,
+    diagnostics: Arc<Mutex<Vec<Diagnostic>>>,
+}
+
+impl Emitter for Collector {
+    fn emit(&mut self, db: &DiagnosticBuilder<'_>) {
+        let severity = match db.level {
+            Level::Warning => Severity::Warning,
+            Level::Note | Level::Help | Level::Cancelled | Level::FailureNote => Severity::Info,
+            _ => Severity::Error,
+        };
+
+        let (file, line, column) = match db.span.primary_span() {
+            Some(span) => {
+                let loc = self.cm.lookup_char_pos(span.lo);
+                (loc.file.name.to_string(), loc.line as u32, loc.col.0 as u32 + 1)
+            }
+            None => (String::new(), 0, 0),
+        };
+
+        self.diagnostics.lock().unwrap().push(Diagnostic {
+            severity,
+            message: db.message(),
+            file,
+            line,
+            column,
+        });
+    }
+}
+
+fn compile(name: &str, source: impl ToString, config: &Config) -> Compilation {
     let cm = Arc::new(SourceMap::default());
 
     let fm = cm.new_source_file(FileName::Custom(name.into()), source.to_string());
 
-    let handler = Arc::new(Handler::with_tty_emitter(
-        ColorConfig::Auto,
+    let diagnostics = Arc::new(Mutex::new(Vec::new()));
+
+    let handler = Arc::new(Handler::with_emitter(
         true,
         false,
-        Some(cm.clone()),
+        Box::new(Collector {
+            cm: cm.clone(),
+            diagnostics: diagnostics.clone(),
+        }),
     ));
 
     let c = swc::Compiler::new(cm.clone());
@@ -46,11 +128,12 @@ fn compile(name: &str, source: impl ToString) -> Result<String, anyhow::Error> {
         &Options {
             config: swc::config::Config {
                 jsc: JscConfig {
-                    target: EsVersion::Es2017.into(),
+                    target: config.target.into(),
                     external_helpers: false.into(),
                     syntax: Some(swc_ecma_parser::Syntax::Typescript(
                         swc_ecma_parser::TsConfig {
-                            tsx: true,
+                            tsx: config.tsx || name.ends_with(".tsx"),
+                            decorators: config.decorators,
 
                             ..Default::default()
                         },
@@ -60,11 +143,32 @@ fn compile(name: &str, source: impl ToString) -> Result<String, anyhow::Error> {
                 },
                 ..Default::default()
             },
+            source_maps: Some(swc::config::SourceMapsConfig::Bool(true)),
             ..Default::default()
         },
-    )?;
+    );
+
+    let mut diagnostics = std::mem::take(&mut *diagnostics.lock().unwrap());
 
-    Ok(output.code)
+    match output {
+        Ok(output) => Compilation::Success(Output {
+            code: output.code,
+            source_map: output.map,
+            diagnostics,
+        }),
+        Err(err) => {
+            if diagnostics.is_empty() {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    message: err.to_string(),
+                    file: name.to_string(),
+                    line: 0,
+                    column: 0,
+                });
+            }
+            Compilation::Failure(diagnostics)
+        }
+    }
 }
 
 fn main() {}
@@ -32,3 +32,16 @@ export async function main(path: string, arg: unknown) {
     throw e;
   }
 }
+
+export async function evaluate(source: string) {
+  try {
+    const ret = await (0, eval)(source);
+    if (ret !== undefined) {
+      console.log(ret);
+    }
+    await awaitAllTasks();
+  } catch (e) {
+    await awaitAllTasks();
+    throw e;
+  }
+}
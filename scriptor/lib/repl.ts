@@ -0,0 +1,45 @@
+import { format } from "util";
+import { awaitAllTasks } from "tasks";
+
+const AsyncFunction = Object.getPrototypeOf(async function () {}).constructor;
+
+function isExpression(source: string): boolean {
+  try {
+    new AsyncFunction(`return (${source}\n)`);
+    return true;
+  } catch {
+    return false;
+  }
+}
+
+export interface Transformed {
+  code: string;
+  wrapped: boolean;
+}
+
+/**
+ * Wrap input awaiting something in an async function. Imports are already
+ * rewritten to dynamic ones, and `body` is `code` followed by copying its
+ * top level declarations to the global object
+ */
+export function transform(code: string, body: string): Transformed {
+  if (isExpression(code)) {
+    return { code: `(async () => (${code}\n))()`, wrapped: true };
+  }
+
+  return { code: `(async () => { ${body}\n })()`, wrapped: true };
+}
+
+export async function inspect(
+  value: unknown,
+  wrapped: boolean
+): Promise<string | undefined> {
+  try {
+    const ret = wrapped ? await value : value;
+    await awaitAllTasks();
+    return ret === undefined ? undefined : format(ret, true);
+  } catch (e) {
+    await awaitAllTasks();
+    throw e;
+  }
+}
@@ -0,0 +1,304 @@
+//! Importing files that are not scripts: json, text and raw bytes.
+//!
+//! `.json` and `.txt` files are picked by extension, anything else is imported
+//! with an attribute (`import text from "./template.html" with { type: "text" }`)
+//! or the equivalent prefixed specifier (`"text:./template.html"`). The
+//! bundled QuickJS cannot parse import attributes, so scripts are rewritten to
+//! the prefixed form before they are compiled.
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use rquickjs::{Ctx, Error, Loaded, Loader, Module, Resolver, Result, TypedArray};
+
+use crate::{lexer::Tokens, utils::normalize_path};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum AssetKind {
+    Json,
+    Text,
+    Bytes,
+}
+
+impl AssetKind {
+    fn from_type(ty: &str) -> Option<AssetKind> {
+        match ty {
+            "json" => Some(AssetKind::Json),
+            "text" => Some(AssetKind::Text),
+            "bytes" => Some(AssetKind::Bytes),
+            _ => None,
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            AssetKind::Json => "json",
+            AssetKind::Text => "text",
+            AssetKind::Bytes => "bytes",
+        }
+    }
+
+    pub(crate) fn from_extension(path: &str) -> Option<AssetKind> {
+        match Path::new(path).extension()?.to_str()? {
+            "json" => Some(AssetKind::Json),
+            "txt" => Some(AssetKind::Text),
+            _ => None,
+        }
+    }
+
+    /// Split `text:./file.html` into its kind and path
+    pub(crate) fn split(name: &str) -> Option<(AssetKind, &str)> {
+        let (prefix, rest) = name.split_once(':')?;
+        Some((AssetKind::from_type(prefix)?, rest))
+    }
+}
+
+/// Resolves and loads `json:`, `text:` and `bytes:` specifiers, as well as
+/// `.json` and `.txt` files
+#[derive(Clone, Debug, Default)]
+pub struct AssetLoader;
+
+impl Resolver for AssetLoader {
+    fn resolve<'js>(&mut self, _ctx: Ctx<'js>, base: &str, name: &str) -> Result<String> {
+        let (kind, path) = match AssetKind::split(name) {
+            Some(split) => split,
+            None => return Err(Error::new_resolving(base, name)),
+        };
+
+        let path = if path.starts_with('/') {
+            PathBuf::from(path)
+        } else if path.starts_with("./") || path.starts_with("../") {
+            let base = Path::new(base).parent().unwrap_or_else(|| Path::new(""));
+            normalize_path(&base.join(path))
+        } else {
+            return Err(Error::new_resolving_message(
+                base,
+                name,
+                "asset imports must be relative or absolute paths",
+            ));
+        };
+
+        if !path.is_file() {
+            return Err(Error::new_resolving(base, name));
+        }
+
+        Ok(format!("{}:{}", kind.prefix(), path.to_string_lossy()))
+    }
+}
+
+impl Loader for AssetLoader {
+    fn load<'js>(&mut self, ctx: Ctx<'js>, name: &str) -> Result<Module<'js, Loaded<()>>> {
+        let (kind, path) = match AssetKind::split(name) {
+            Some(split) => split,
+            None => match AssetKind::from_extension(name) {
+                Some(kind) => (kind, name),
+                None => return Err(Error::new_loading(name)),
+            },
+        };
+
+        let source = match kind {
+            AssetKind::Json | AssetKind::Text => text_source(kind, path)?,
+            AssetKind::Bytes => {
+                // Handed over through a global that the module removes again
+                let key = format!("__bytes:{}", path);
+                let bytes = TypedArray::<u8>::new_copy(ctx, std::fs::read(path)?)?;
+                ctx.globals().set(key.as_str(), bytes)?;
+
+                let key = js_string(&key);
+                format!(
+                    "const bytes = globalThis[{0}];\ndelete globalThis[{0}];\nexport default bytes;\n",
+                    key
+                )
+            }
+        };
+
+        Ok(Module::new(ctx, name, source)?.into_loaded())
+    }
+}
+
+fn text_source(kind: AssetKind, path: &str) -> Result<String> {
+    let content = js_string(&std::fs::read_to_string(path)?);
+    Ok(match kind {
+        AssetKind::Json => format!("export default JSON.parse({});\n", content),
+        _ => format!("export default {};\n", content),
+    })
+}
+
+/// Source of a module exporting the asset at `path` by value, as needed in bundles
+pub(crate) fn inline_source(kind: AssetKind, path: &str) -> Result<String> {
+    match kind {
+        AssetKind::Bytes => {
+            let bytes = std::fs::read(path)?
+                .iter()
+                .map(|b| b.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            Ok(format!("export default new Uint8Array([{}]);\n", bytes))
+        }
+        kind => text_source(kind, path),
+    }
+}
+
+/// Quote `input` as a javascript string literal
+fn js_string(input: &str) -> String {
+    let mut out = String::with_capacity(input.len() + 2);
+    out.push('"');
+    for c in input.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{2028}' => out.push_str("\\u2028"),
+            '\u{2029}' => out.push_str("\\u2029"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Rewrite `from "./data.txt" with { type: "text" }` (or `assert { ... }`) to
+/// `from "text:./data.txt"`. Only string literals directly following `from` or
+/// `import` are considered, never text in comments or other strings. Lines
+/// are kept where they are, so source maps still apply
+pub fn rewrite_import_attributes(source: &str) -> Cow<'_, str> {
+    let mut out = String::new();
+    let mut last = 0;
+
+    let tokens = Tokens::new(source).collect::<Vec<_>>();
+
+    for window in tokens.windows(3) {
+        let (keyword, specifier, attributes) = (&window[0], &window[1], &window[2]);
+        if keyword.start < last
+            || !(keyword.is_word("from") || keyword.is_word("import"))
+            || !(attributes.is_word("with") || attributes.is_word("assert"))
+        {
+            continue;
+        }
+
+        let value = match specifier.string_value() {
+            Some(value) => value,
+            None => continue,
+        };
+
+        let (kind, consumed) = match parse_attributes(&source[attributes.start..]) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+
+        let quote = &specifier.text[..1];
+        let end = attributes.start + consumed;
+
+        out.push_str(&source[last..specifier.start]);
+        out.push_str(quote);
+        out.push_str(kind.prefix());
+        out.push(':');
+        out.push_str(value);
+        out.push_str(quote);
+        out.extend(source[specifier.end()..end].matches('\n'));
+        last = end;
+    }
+
+    if last == 0 {
+        Cow::Borrowed(source)
+    } else {
+        out.push_str(&source[last..]);
+        Cow::Owned(out)
+    }
+}
+
+/// Parse ` with { type: "text" }`, returning the kind and the number of bytes consumed
+fn parse_attributes(input: &str) -> Option<(AssetKind, usize)> {
+    let trimmed = input.trim_start();
+    let rest = trimmed
+        .strip_prefix("with")
+        .or_else(|| trimmed.strip_prefix("assert"))?;
+    let rest = rest.trim_start().strip_prefix('{')?;
+    let close = rest.find('}')?;
+
+    let body = rest[..close].trim();
+    let value = body
+        .strip_prefix("type")?
+        .trim_start()
+        .strip_prefix(':')?
+        .trim();
+    let value = value.trim_end_matches(',').trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))?;
+
+    let kind = AssetKind::from_type(value)?;
+    let consumed = input.len() - rest.len() + close + 1;
+
+    Some((kind, consumed))
+}
+
+/// Script loader for `.js` and `.mjs` files that understands import attributes
+#[derive(Clone, Debug)]
+pub struct ScriptFileLoader {
+    extensions: Vec<String>,
+}
+
+impl Default for ScriptFileLoader {
+    fn default() -> Self {
+        ScriptFileLoader {
+            extensions: vec!["js".to_string(), "mjs".to_string()],
+        }
+    }
+}
+
+impl Loader for ScriptFileLoader {
+    fn load<'js>(&mut self, ctx: Ctx<'js>, path: &str) -> Result<Module<'js, Loaded<()>>> {
+        let handled = Path::new(path)
+            .extension()
+            .map(|ext| self.extensions.iter().any(|m| ext == m.as_str()))
+            .unwrap_or_default();
+
+        if !handled {
+            return Err(Error::new_loading(path));
+        }
+
+        let source = std::fs::read_to_string(path)?;
+        let source = rewrite_import_attributes(&source);
+
+        Ok(Module::new(ctx, path, source.as_bytes())?.into_loaded())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrites_import_attributes() {
+        assert_eq!(
+            rewrite_import_attributes(
+                "import page from './page.html' with { type: \"text\" };\nexport { default } from \"./data.bin\" assert { type: 'bytes' };"
+            ),
+            "import page from 'text:./page.html';\nexport { default } from \"bytes:./data.bin\";"
+        );
+    }
+
+    #[test]
+    fn leaves_strings_and_comments_alone() {
+        let source = "// import a from './a.html' with { type: \"text\" }\nconst s = \"from './b.html' with { type: 'text' }\";";
+        assert!(matches!(
+            rewrite_import_attributes(source),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn keeps_lines() {
+        assert_eq!(
+            rewrite_import_attributes("import a from './a.html' with {\n  type: 'text'\n};\nx;"),
+            "import a from 'text:./a.html'\n\n;\nx;"
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use clap::Subcommand;
+use scriptor::{loaders, DirConfig};
+use std::path::PathBuf;
+
+#[derive(Subcommand)]
+pub enum LoaderCommand {
+    /// Install a loader from a local wasm file
+    Install {
+        path: PathBuf,
+        /// Expected sha256 of the wasm file
+        #[clap(long)]
+        sha256: Option<String>,
+    },
+    /// List installed loaders and the extensions they handle
+    List,
+    /// Remove an installed loader
+    Remove { name: String },
+    /// Check an installed loader against a sha256 checksum
+    Verify { name: String, sha256: String },
+}
+
+pub async fn run(root: Option<PathBuf>, command: LoaderCommand) -> anyhow::Result<()> {
+    let cfg = DirConfig::new(root).await?;
+
+    match command {
+        LoaderCommand::Install { path, sha256 } => {
+            let loader = loaders::install(&cfg, path, sha256.as_deref()).await?;
+            println!(
+                "installed {} ({}) sha256:{}",
+                loader.name,
+                loader.extensions.join(", "),
+                loader.checksum
+            );
+        }
+        LoaderCommand::List => {
+            for loader in loaders::list(&cfg).await? {
+                match loader {
+                    Ok(loader) => println!(
+                        "{}\tv{}\t{}\tsha256:{}",
+                        loader.name,
+                        loader.version,
+                        loader.extensions.join(", "),
+                        loader.checksum
+                    ),
+                    Err(err) => eprintln!("{:#}", err),
+                }
+            }
+        }
+        LoaderCommand::Remove { name } => {
+            loaders::remove(&cfg, &name).await?;
+            println!("removed {}", name);
+        }
+        LoaderCommand::Verify { name, sha256 } => {
+            loaders::verify(&cfg, &name, &sha256).await?;
+            println!("{}: ok", name);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,298 @@
+mod loader;
+mod repl;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use scriptor::{BundleFormat, ScriptBundle, Vm, VmBuilder, WatchOptions, BUNDLE_EXTENSION};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+#[derive(Parser)]
+#[clap(
+    name = "scriptor",
+    version,
+    about = "Run javascript and typescript scripts"
+)]
+struct Cli {
+    #[clap(flatten)]
+    options: Options,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct Options {
+    /// Working directory used to resolve script imports
+    #[clap(long, global = true)]
+    cwd: Option<PathBuf>,
+    /// Root directory for loaders and cache
+    #[clap(long, global = true)]
+    root: Option<PathBuf>,
+    /// Builtin modules to enable. Defaults to all of them
+    #[clap(short, long = "module", value_enum, global = true)]
+    modules: Vec<BuiltinModule>,
+    /// Always transpile modules instead of using the on disk cache
+    #[clap(long, global = true)]
+    no_cache: bool,
+    /// Import map to use instead of import_map.json in the working directory
+    #[clap(long, global = true)]
+    import_map: Option<PathBuf>,
+    /// Map a specifier or prefix to a path or builtin module, as in `@lib/=./src/lib/`
+    #[clap(long = "alias", value_name = "SPECIFIER=TARGET", value_parser = parse_alias, global = true)]
+    aliases: Vec<(String, String)>,
+    /// Only load remote modules from the cache
+    #[clap(long, global = true)]
+    offline: bool,
+    /// Always load modules from source, ignoring precompiled bytecode
+    #[clap(long, global = true)]
+    no_bytecode: bool,
+    /// Lockfile to use instead of scriptor.lock in the working directory
+    #[clap(long, global = true)]
+    lock: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a script file, or a bundle
+    Run {
+        path: PathBuf,
+        /// Arguments passed to the main function of the script
+        #[clap(last = true)]
+        args: Vec<String>,
+        /// Run the script again whenever a file it loaded changes
+        #[clap(short, long)]
+        watch: bool,
+    },
+    /// Bundle a script and everything it imports into a single file
+    Bundle {
+        entry: PathBuf,
+        /// Defaults to the entry with a .bundle extension
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// Store QuickJS bytecode instead of javascript sources
+        #[clap(long)]
+        bytecode: bool,
+    },
+    /// Precompile a script and the files it imports to bytecode stored next to them
+    Compile { entry: PathBuf },
+    /// Evaluate an inline expression
+    Eval { source: String },
+    /// Start an interactive session
+    Repl,
+    /// Manage installed wasm loaders
+    Loader {
+        #[clap(subcommand)]
+        command: loader::LoaderCommand,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum BuiltinModule {
+    Fs,
+    Os,
+    Http,
+}
+
+impl Options {
+    fn builder(&self) -> VmBuilder {
+        let mut builder = Vm::build();
+
+        let all = self.modules.is_empty();
+        let enabled = |module| all || self.modules.contains(&module);
+
+        if enabled(BuiltinModule::Fs) {
+            builder.add_module(scriptor::fs::Module);
+        }
+
+        if enabled(BuiltinModule::Os) {
+            builder.add_module(scriptor::os::Module);
+        }
+
+        if enabled(BuiltinModule::Http) {
+            builder.add_module(scriptor::http::Module);
+        }
+
+        if let Some(cwd) = &self.cwd {
+            builder.cwd(cwd);
+        }
+
+        if let Some(root) = &self.root {
+            builder.root(root);
+        }
+
+        builder.transpile_cache(!self.no_cache);
+        builder.load_bytecode(!self.no_bytecode);
+
+        // Paths given on the command line are relative to where it is run,
+        // not to --cwd
+        if let Some(import_map) = &self.import_map {
+            builder.import_map_file(absolute(import_map));
+        }
+
+        for (specifier, target) in &self.aliases {
+            builder.alias(specifier, target);
+        }
+
+        builder.offline(self.offline);
+
+        if let Some(lock) = &self.lock {
+            builder.lockfile(absolute(lock));
+        }
+
+        builder
+    }
+}
+
+/// `path` joined to the working directory of the process
+fn absolute(path: &Path) -> PathBuf {
+    match std::env::current_dir() {
+        Ok(cwd) => cwd.join(path),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+fn parse_alias(input: &str) -> Result<(String, String), String> {
+    match input.split_once('=') {
+        Some((specifier, target)) if !specifier.is_empty() && !target.is_empty() => {
+            Ok((specifier.to_string(), target.to_string()))
+        }
+        _ => Err(format!("expected SPECIFIER=TARGET, got {:?}", input)),
+    }
+}
+
+async fn run(cli: Cli) -> scriptor::Result<i32> {
+    let mut builder = cli.options.builder();
+
+    match cli.command {
+        Command::Run { path, args, watch } => {
+            let bundle = if path.extension().map(|ext| ext == BUNDLE_EXTENSION) == Some(true) {
+                Some(ScriptBundle::open(&path)?)
+            } else {
+                None
+            };
+
+            let exe = std::env::args().next().unwrap_or_default();
+            let argv = [exe, path.to_string_lossy().to_string()]
+                .into_iter()
+                .chain(args.iter().cloned())
+                .collect::<Vec<_>>();
+
+            if watch {
+                let build = || {
+                    let mut builder = cli.options.builder();
+                    builder.argv(argv.clone());
+                    if let Some(bundle) = &bundle {
+                        builder.add_bundle(bundle.clone());
+                    }
+                    builder
+                };
+
+                let path = match &bundle {
+                    Some(bundle) => PathBuf::from(bundle.entry()),
+                    None => path.clone(),
+                };
+
+                scriptor::watch_main(
+                    build,
+                    &path,
+                    args,
+                    &WatchOptions::default(),
+                    |ret| match ret {
+                        Ok(code) => eprintln!("exited with code {}, waiting for changes", code),
+                        Err(err) => eprintln!("{}\nwaiting for changes", err),
+                    },
+                )
+                .await?;
+
+                return Ok(0);
+            }
+
+            builder.argv(argv);
+
+            let path = match bundle {
+                Some(bundle) => {
+                    let entry = PathBuf::from(bundle.entry());
+                    builder.add_bundle(bundle);
+                    entry
+                }
+                None => path,
+            };
+
+            let mut vm = builder.build().await?;
+            vm.run_main(path, args).await
+        }
+        Command::Bundle {
+            entry,
+            output,
+            bytecode,
+        } => {
+            let format = if bytecode {
+                BundleFormat::Bytecode
+            } else {
+                BundleFormat::Source
+            };
+
+            let vm = builder.build().await?;
+            let bundle = vm.bundle(&entry, format)?;
+
+            let output = output.unwrap_or_else(|| entry.with_extension(BUNDLE_EXTENSION));
+            bundle.write(&output)?;
+
+            eprintln!(
+                "bundled {} modules into {}",
+                bundle.modules().count(),
+                output.display()
+            );
+
+            Ok(0)
+        }
+        Command::Compile { entry } => {
+            let vm = builder.build().await?;
+            for path in vm.precompile(&entry)? {
+                eprintln!("wrote {}", path.display());
+            }
+            Ok(0)
+        }
+        Command::Eval { source } => {
+            let mut vm = builder.build().await?;
+            vm.eval(source).await
+        }
+        Command::Repl => {
+            let mut vm = builder.build().await?;
+            repl::run(&mut vm).await
+        }
+        Command::Loader { .. } => unreachable!("loader commands are run by main"),
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    // Loader management does not need a vm, and reports its own errors
+    if let Command::Loader { command } = cli.command {
+        return match loader::run(cli.options.root, command).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{:#}", err);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let ret = tokio::task::LocalSet::default()
+        .run_until(async move { run(cli).await })
+        .await;
+
+    match ret {
+        // Codes outside what the platform can report are a failure, not wrapped around
+        Ok(code) => u8::try_from(code)
+            .map(ExitCode::from)
+            .unwrap_or(ExitCode::FAILURE),
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use rustyline::{error::ReadlineError, Editor};
+use scriptor::{repl::is_incomplete, Repl, Vm};
+use std::path::Path;
+
+pub async fn run(vm: &mut Vm) -> scriptor::Result<i32> {
+    let history = vm.config().config_dir().join("history.txt");
+
+    let mut repl = Repl::new(vm)?;
+    let mut editor = Editor::<()>::new();
+
+    // A missing history file just means this is the first session
+    let _ = editor.load_history(&history);
+
+    let mut buffer = String::new();
+
+    loop {
+        let prompt = if buffer.is_empty() { "> " } else { "... " };
+
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, err).into());
+            }
+        };
+
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer.push_str(&line);
+
+        if is_incomplete(&buffer) {
+            continue;
+        }
+
+        let source = std::mem::take(&mut buffer);
+        if source.trim().is_empty() {
+            continue;
+        }
+
+        editor.add_history_entry(source.as_str());
+
+        match repl.eval(&source).await {
+            Ok(Some(output)) => println!("{}", output),
+            Ok(None) => {}
+            Err(err) if repl.exit_code().is_none() => eprintln!("{}", err),
+            Err(_) => {}
+        }
+
+        if let Some(code) = repl.exit_code() {
+            save_history(&mut editor, &history);
+            return Ok(code);
+        }
+    }
+
+    save_history(&mut editor, &history);
+
+    Ok(0)
+}
+
+fn save_history(editor: &mut Editor<()>, path: &Path) {
+    if let Err(err) = editor.save_history(path) {
+        log::warn!("could not save history: {}", err);
+    }
+}
@@ -0,0 +1,537 @@
+//! Bundling a script and everything it imports into a single file.
+//!
+//! Starting at an entry file, imports are resolved with the resolvers of the
+//! vm doing the bundling and transpiled with its loaders. Imports that do not
+//! resolve to a file, like builtin modules, are left to the vm running the
+//! bundle. A bundle is added to a vm with `VmBuilder::add_bundle`.
+//!
+//! The file starts with a line of json describing the modules. Sources are
+//! stored in it as is, bytecode follows it.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use rquickjs::{Ctx, Error, Loaded, Loader, Module, Resolver, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::{inline_source, rewrite_import_attributes, AssetKind},
+    bytecode::compile,
+    lexer::Tokens,
+    utils::normalize_path,
+    SourceMaps,
+};
+
+#[cfg(feature = "http")]
+use crate::remote::{is_remote, RemoteModules};
+
+#[cfg(feature = "wasm")]
+use crate::wasm_loader::WasmLoaders;
+
+/// Extension of bundle files
+pub const BUNDLE_EXTENSION: &str = "bundle";
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BundleFormat {
+    /// Javascript sources
+    Source,
+    /// QuickJS bytecode, which skips parsing when the bundle is loaded
+    Bytecode,
+}
+
+#[derive(Clone, Debug)]
+enum Code {
+    Source(String),
+    Bytecode(Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+struct BundledModule {
+    /// Module names of the specifiers imported by the module
+    imports: BTreeMap<String, String>,
+    code: Code,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Header {
+    format: BundleFormat,
+    entry: String,
+    modules: BTreeMap<String, HeaderModule>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct HeaderModule {
+    imports: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    /// Offset and length of the bytecode after the header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bytecode: Option<(usize, usize)>,
+}
+
+/// A script and the modules it imports
+#[derive(Clone, Debug)]
+pub struct ScriptBundle {
+    format: BundleFormat,
+    entry: String,
+    modules: BTreeMap<String, BundledModule>,
+}
+
+impl ScriptBundle {
+    /// Name of the entry module, to pass to `Vm::run_main`
+    pub fn entry(&self) -> &str {
+        &self.entry
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &str> {
+        self.modules.keys().map(String::as_str)
+    }
+
+    /// Format of the bundle, which is kept in its header
+    pub fn format(&self) -> BundleFormat {
+        self.format
+    }
+
+    /// Compile every module to bytecode
+    pub fn into_bytecode(mut self) -> Result<ScriptBundle> {
+        let sources = self
+            .modules
+            .iter()
+            .filter_map(|(name, module)| match &module.code {
+                Code::Source(source) => Some((name.as_str(), source.as_str())),
+                Code::Bytecode(_) => None,
+            });
+
+        for (name, bytecode) in compile(sources)? {
+            if let Some(module) = self.modules.get_mut(&name) {
+                module.code = Code::Bytecode(bytecode);
+            }
+        }
+
+        self.format = BundleFormat::Bytecode;
+        Ok(self)
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut blobs = Vec::new();
+        let mut modules = BTreeMap::new();
+
+        for (name, module) in &self.modules {
+            let (source, bytecode) = match &module.code {
+                Code::Source(source) => (Some(source.clone()), None),
+                Code::Bytecode(bytes) => {
+                    let range = (blobs.len(), bytes.len());
+                    blobs.extend_from_slice(bytes);
+                    (None, Some(range))
+                }
+            };
+
+            modules.insert(
+                name.clone(),
+                HeaderModule {
+                    imports: module.imports.clone(),
+                    source,
+                    bytecode,
+                },
+            );
+        }
+
+        let header = Header {
+            format: self.format,
+            entry: self.entry.clone(),
+            modules,
+        };
+
+        let mut out = serde_json::to_vec(&header)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        out.push(b'\n');
+        out.extend(blobs);
+
+        Ok(out)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<ScriptBundle> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let end = bytes
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| invalid("missing bundle header".to_string()))?;
+
+        let header: Header =
+            serde_json::from_slice(&bytes[..end]).map_err(|err| invalid(err.to_string()))?;
+        let blobs = &bytes[end + 1..];
+
+        let mut modules = BTreeMap::new();
+        for (name, module) in header.modules {
+            let code = match (module.source, module.bytecode) {
+                (Some(source), _) => Code::Source(source),
+                (None, Some((offset, len))) => offset
+                    .checked_add(len)
+                    .and_then(|end| blobs.get(offset..end))
+                    .map(|bytes| Code::Bytecode(bytes.to_vec()))
+                    .ok_or_else(|| invalid(format!("bytecode of {} is out of bounds", name)))?,
+                (None, None) => return Err(invalid(format!("module {} has no code", name))),
+            };
+
+            modules.insert(
+                name,
+                BundledModule {
+                    imports: module.imports,
+                    code,
+                },
+            );
+        }
+
+        Ok(ScriptBundle {
+            format: header.format,
+            entry: header.entry,
+            modules,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<ScriptBundle> {
+        let path = path.as_ref();
+        ScriptBundle::from_bytes(&std::fs::read(path)?).map_err(|err| {
+            io::Error::new(err.kind(), format!("{}: {}", path.to_string_lossy(), err))
+        })
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_bytes()?)
+    }
+}
+
+impl Resolver for ScriptBundle {
+    fn resolve<'js>(&mut self, _ctx: Ctx<'js>, base: &str, name: &str) -> Result<String> {
+        if let Some(target) = self.modules.get(base).and_then(|m| m.imports.get(name)) {
+            return Ok(target.clone());
+        }
+
+        // The entry point, or another module by the name it was bundled under
+        let name = name.strip_prefix("./").unwrap_or(name);
+        if self.modules.contains_key(name) {
+            return Ok(name.to_string());
+        }
+
+        Err(Error::new_resolving(base, name))
+    }
+}
+
+impl Loader for ScriptBundle {
+    fn load<'js>(&mut self, ctx: Ctx<'js>, name: &str) -> Result<Module<'js, Loaded<()>>> {
+        match self.modules.get(name).map(|m| &m.code) {
+            Some(Code::Source(source)) => {
+                Ok(Module::new(ctx, name, source.as_bytes())?.into_loaded())
+            }
+            Some(Code::Bytecode(bytes)) => Ok(Module::read_object(ctx, bytes)?.into_loaded()),
+            None => Err(Error::new_loading(name)),
+        }
+    }
+}
+
+/// The resolvers of a vm, shared with the bundler
+#[derive(Clone)]
+pub(crate) struct SharedResolver(Rc<RefCell<dyn Resolver>>);
+
+impl SharedResolver {
+    pub fn new<R: Resolver + 'static>(resolver: R) -> SharedResolver {
+        SharedResolver(Rc::new(RefCell::new(resolver)))
+    }
+}
+
+impl Resolver for SharedResolver {
+    fn resolve<'js>(&mut self, ctx: Ctx<'js>, base: &str, name: &str) -> Result<String> {
+        self.0.borrow_mut().resolve(ctx, base, name)
+    }
+}
+
+/// Produces the javascript source of modules the way the loaders of a vm would
+#[derive(Clone)]
+pub(crate) struct Sources {
+    pub cwd: PathBuf,
+    /// Where the maps of transpiled sources are registered
+    pub source_maps: SourceMaps,
+    #[cfg(feature = "wasm")]
+    pub wasm: Option<WasmLoaders>,
+    #[cfg(feature = "http")]
+    pub remote: RemoteModules,
+}
+
+impl Sources {
+    /// Source of the module `name`, or `None` for modules that are not files
+    fn source(&self, name: &str) -> Result<Option<String>> {
+        if let Some((kind, path)) = AssetKind::split(name) {
+            return inline_source(kind, path).map(Some);
+        }
+
+        #[cfg(feature = "http")]
+        if is_remote(name) {
+            return self.remote.source(name).map(Some);
+        }
+
+        if !Path::new(name).is_file() {
+            return Ok(None);
+        }
+
+        if let Some(kind) = AssetKind::from_extension(name) {
+            return inline_source(kind, name).map(Some);
+        }
+
+        let ext = Path::new(name)
+            .extension()
+            .map(|m| m.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        match ext.as_str() {
+            "js" | "mjs" => {
+                let source = std::fs::read_to_string(name)?;
+                Ok(Some(rewrite_import_attributes(&source).into_owned()))
+            }
+            #[cfg(feature = "typescript")]
+            ext if crate::typescript_loader::TYPESCRIPT_EXTENSIONS.contains(&ext) => {
+                let source = std::fs::read_to_string(name)?;
+                let source = rewrite_import_attributes(&source);
+                let (code, map) = crate::typescript_loader::transpile(name, source)?;
+                if let Some(map) = map {
+                    self.source_maps.insert(name, &map);
+                }
+                Ok(Some(code))
+            }
+            #[cfg(feature = "wasm")]
+            "wasm" => Err(Error::new_loading_message(
+                name,
+                "wasm modules can not be bundled or precompiled",
+            )),
+            #[cfg(feature = "wasm")]
+            _ if self.wasm.is_some() => self
+                .wasm
+                .as_ref()
+                .map(|wasm| wasm.transform_file(name))
+                .transpose(),
+            _ => Err(Error::new_loading(name)),
+        }
+    }
+
+    /// Name of the module in the bundle: paths relative to the working
+    /// directory, everything else as is
+    fn key(&self, name: &str) -> String {
+        let (prefix, path) = match AssetKind::split(name) {
+            Some(_) => name.split_once(':').unwrap(),
+            None => ("", name),
+        };
+
+        let path = Path::new(path);
+        if !path.is_file() {
+            return name.to_string();
+        }
+
+        let path = normalize_path(&self.cwd.join(path));
+        let path = path.strip_prefix(&self.cwd).unwrap_or(&path);
+        let path = path.to_string_lossy().replace('\\', "/");
+
+        if prefix.is_empty() {
+            path
+        } else {
+            format!("{}:{}", prefix, path)
+        }
+    }
+}
+
+/// A module reached from an entry point
+pub(crate) struct GraphModule {
+    pub name: String,
+    pub source: String,
+    /// Specifiers imported by the module, with the names they resolve to
+    pub imports: Vec<(String, String)>,
+}
+
+/// The module at `entry` followed by every module it imports, directly or not,
+/// that is backed by a file or url
+pub(crate) fn graph(
+    ctx: Ctx<'_>,
+    resolver: &mut SharedResolver,
+    sources: &Sources,
+    entry: &Path,
+) -> Result<Vec<GraphModule>> {
+    // Resolved the way `Vm::run_main` imports it
+    let entry = resolver.resolve(ctx, "main", &entry.to_string_lossy())?;
+
+    let mut modules = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut queue = vec![entry.clone()];
+
+    while let Some(name) = queue.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+
+        let source = match sources.source(&name)? {
+            Some(source) => source,
+            None if name == entry => return Err(Error::new_loading(&name)),
+            None => continue,
+        };
+
+        let mut imports = Vec::new();
+        for specifier in scan_imports(&source) {
+            let resolved = resolver.resolve(ctx, &name, &specifier)?;
+            queue.push(resolved.clone());
+            imports.push((specifier, resolved));
+        }
+
+        modules.push(GraphModule {
+            name,
+            source,
+            imports,
+        });
+    }
+
+    Ok(modules)
+}
+
+/// Bundle the module at `entry` and everything it imports
+pub(crate) fn bundle(
+    ctx: Ctx<'_>,
+    resolver: &mut SharedResolver,
+    sources: &Sources,
+    entry: &Path,
+) -> Result<ScriptBundle> {
+    let graph = graph(ctx, resolver, sources, entry)?;
+    let entry = sources.key(&graph[0].name);
+
+    let modules = graph
+        .into_iter()
+        .map(|module| {
+            let imports = module
+                .imports
+                .iter()
+                .map(|(specifier, resolved)| (specifier.clone(), sources.key(resolved)))
+                .collect();
+
+            (
+                sources.key(&module.name),
+                BundledModule {
+                    imports,
+                    code: Code::Source(module.source),
+                },
+            )
+        })
+        .collect();
+
+    Ok(ScriptBundle {
+        format: BundleFormat::Source,
+        entry,
+        modules,
+    })
+}
+
+/// Specifiers of the static imports and re-exports in `source`, and of dynamic
+/// imports of a single string literal
+fn scan_imports(source: &str) -> Vec<String> {
+    let tokens = Tokens::new(source).collect::<Vec<_>>();
+    let mut specifiers = Vec::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        let next = |n: usize| tokens.get(i + n);
+
+        let specifier = if token.is_word("from") {
+            next(1)
+        } else if token.is_word("import") {
+            match next(1) {
+                // `import("x")`, but not `import("x" + y)`
+                Some(open) if open.is_punct('(') => {
+                    next(3).filter(|close| close.is_punct(')')).and(next(2))
+                }
+                // `import "x"`
+                other => other,
+            }
+        } else {
+            None
+        };
+
+        if let Some(value) = specifier.and_then(|token| token.string_value()) {
+            specifiers.push(value.to_string());
+        }
+    }
+
+    specifiers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle() -> ScriptBundle {
+        let mut modules = BTreeMap::new();
+        modules.insert(
+            "main.js".to_string(),
+            BundledModule {
+                imports: [("./lib.js".to_string(), "lib.js".to_string())].into(),
+                code: Code::Source("import './lib.js';".to_string()),
+            },
+        );
+        modules.insert(
+            "lib.js".to_string(),
+            BundledModule {
+                imports: BTreeMap::new(),
+                code: Code::Bytecode(vec![1, 2, 3]),
+            },
+        );
+
+        ScriptBundle {
+            format: BundleFormat::Bytecode,
+            entry: "main.js".to_string(),
+            modules,
+        }
+    }
+
+    #[test]
+    fn round_trips_bundles() {
+        let bytes = bundle().to_bytes().unwrap();
+        let bundle = ScriptBundle::from_bytes(&bytes).unwrap();
+
+        assert_eq!(bundle.format(), BundleFormat::Bytecode);
+        assert_eq!(bundle.entry(), "main.js");
+        assert_eq!(bundle.modules().collect::<Vec<_>>(), ["lib.js", "main.js"]);
+        assert_eq!(bundle.modules["main.js"].imports["./lib.js"], "lib.js");
+        assert!(matches!(&bundle.modules["lib.js"].code, Code::Bytecode(b) if b == &[1, 2, 3]));
+        assert!(
+            matches!(&bundle.modules["main.js"].code, Code::Source(s) if s == "import './lib.js';")
+        );
+    }
+
+    #[test]
+    fn rejects_bytecode_out_of_bounds() {
+        let header = format!(
+            r#"{{"format":"bytecode","entry":"a","modules":{{"a":{{"imports":{{}},"bytecode":[1,{}]}}}}}}"#,
+            usize::MAX
+        );
+        let err = ScriptBundle::from_bytes(format!("{}\n\0\0", header).as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn scans_imports() {
+        let source = r#"
+            import a from "./a.js";
+            import "./b.js";
+            export * from './c.js';
+            const d = await import("./d.js");
+            const e = await import("./e" + name);
+            // import f from "./f.js";
+            const g = "import h from './h.js'";
+            const i = /["']/.test(x) ? import.meta.url : "";
+        "#;
+
+        assert_eq!(
+            scan_imports(source),
+            ["./a.js", "./b.js", "./c.js", "./d.js"]
+        );
+    }
+}
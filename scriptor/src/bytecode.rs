@@ -0,0 +1,208 @@
+//! Precompiled QuickJS bytecode stored next to the source of a module.
+//!
+//! `lib.ts` is compiled to `lib.ts.qjsc`, which starts with a hash of the
+//! configuration of the vm that compiled it, and the size and modification
+//! time of the source it was compiled from. `BytecodeLoader` loads it in
+//! place of the source as long as those still match. The source map of a
+//! transpiled module is kept in `lib.ts.qjsc.map`.
+
+use std::{
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use rquickjs::{Context, Ctx, Error, Loaded, Loader, Module, Result, Runtime};
+
+use crate::{
+    utils::{sha256_hex, write_atomic},
+    SourceMaps,
+};
+
+/// Extension added to the file name of the source
+pub const BYTECODE_EXTENSION: &str = "qjsc";
+
+const MAGIC: &[u8; 4] = b"SQJC";
+
+/// Size of the magic, the hex encoded configuration hash, the source size and
+/// the source modification time
+const HEADER_LEN: usize = 4 + 64 + 8 + 16;
+
+/// Path of the bytecode compiled from `path`
+pub fn bytecode_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut file = path.as_ref().as_os_str().to_owned();
+    file.push(".");
+    file.push(BYTECODE_EXTENSION);
+    PathBuf::from(file)
+}
+
+/// Path of the source map of the bytecode compiled from `path`
+fn map_path(path: &Path) -> PathBuf {
+    let mut file = bytecode_path(path).into_os_string();
+    file.push(".map");
+    PathBuf::from(file)
+}
+
+fn stamp(path: &Path, config: &str) -> io::Result<[u8; HEADER_LEN]> {
+    let meta = std::fs::metadata(path)?;
+    let modified = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|m| m.as_nanos())
+        .unwrap_or_default();
+
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(MAGIC);
+    header[4..68].copy_from_slice(sha256_hex(config.as_bytes()).as_bytes());
+    header[68..76].copy_from_slice(&meta.len().to_le_bytes());
+    header[76..].copy_from_slice(&modified.to_le_bytes());
+    Ok(header)
+}
+
+/// Compile every `(name, source)` module to bytecode. The names must be the
+/// ones the modules are loaded by
+pub(crate) fn compile<'a>(
+    modules: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    // Compiling registers the module in its context, so a context of its own is used
+    let rt = Runtime::new()?;
+    let ctx = Context::full(&rt)?;
+
+    ctx.with(|ctx| {
+        modules
+            .into_iter()
+            .map(|(name, source)| {
+                let bytecode = Module::new(ctx, name, source.as_bytes())?.write_object(false)?;
+                Ok((name.to_string(), bytecode))
+            })
+            .collect()
+    })
+}
+
+/// Write `bytecode` compiled from the file at `path` next to it, along with
+/// the source map of the compiled code. `config` identifies the configuration
+/// of the vm that compiled it
+pub(crate) fn write(
+    path: &Path,
+    bytecode: &[u8],
+    config: &str,
+    map: Option<&str>,
+) -> io::Result<PathBuf> {
+    let dest = bytecode_path(path);
+
+    let mut content = stamp(path, config)?.to_vec();
+    content.extend_from_slice(bytecode);
+
+    // A map left by an earlier compilation would no longer match
+    match map {
+        Some(map) => write_atomic(&map_path(path), map)?,
+        None => match std::fs::remove_file(map_path(path)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        },
+    }
+
+    write_atomic(&dest, content)?;
+
+    Ok(dest)
+}
+
+/// Bytecode compiled from the current version of the file at `path` by a vm
+/// configured like this one
+fn read_fresh(path: &Path, config: &str) -> Option<Vec<u8>> {
+    let mut file = std::fs::File::open(bytecode_path(path)).ok()?;
+
+    let mut header = [0; HEADER_LEN];
+    file.read_exact(&mut header).ok()?;
+    if header != stamp(path, config).ok()? {
+        return None;
+    }
+
+    let mut bytecode = Vec::new();
+    file.read_to_end(&mut bytecode).ok()?;
+    Some(bytecode)
+}
+
+/// Loads modules from their precompiled bytecode, when it is up to date
+#[derive(Clone, Default)]
+pub struct BytecodeLoader {
+    config: String,
+    source_maps: SourceMaps,
+}
+
+impl BytecodeLoader {
+    /// Load bytecode compiled by a vm with the configuration `config`,
+    /// registering the source maps stored with it in `source_maps`
+    pub fn new(config: impl Into<String>, source_maps: SourceMaps) -> BytecodeLoader {
+        BytecodeLoader {
+            config: config.into(),
+            source_maps,
+        }
+    }
+}
+
+impl Loader for BytecodeLoader {
+    fn load<'js>(&mut self, ctx: Ctx<'js>, name: &str) -> Result<Module<'js, Loaded<()>>> {
+        let path = Path::new(name);
+        let bytecode = match read_fresh(path, &self.config) {
+            Some(bytecode) => bytecode,
+            None => return Err(Error::new_loading(name)),
+        };
+
+        if let Ok(map) = std::fs::read_to_string(map_path(path)) {
+            self.source_maps.insert(name, &map);
+        }
+
+        Ok(Module::read_object(ctx, &bytecode)?.into_loaded())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A source file `name` in a directory removed with it
+    fn source(name: &str) -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, "export default 1;").unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn reads_fresh_bytecode() {
+        let (_dir, path) = source("fresh.js");
+        write(&path, b"bytecode", "config", None).unwrap();
+
+        assert_eq!(read_fresh(&path, "config"), Some(b"bytecode".to_vec()));
+    }
+
+    #[test]
+    fn ignores_bytecode_of_other_configurations() {
+        let (_dir, path) = source("config.js");
+        write(&path, b"bytecode", "config", None).unwrap();
+
+        assert_eq!(read_fresh(&path, "other config"), None);
+    }
+
+    #[test]
+    fn ignores_bytecode_of_changed_sources() {
+        let (_dir, path) = source("changed.js");
+        write(&path, b"bytecode", "config", None).unwrap();
+        std::fs::write(&path, "export default 2;;").unwrap();
+
+        assert_eq!(read_fresh(&path, "config"), None);
+    }
+
+    #[test]
+    fn keeps_maps_with_the_bytecode() {
+        let (_dir, path) = source("map.ts");
+
+        write(&path, b"bytecode", "config", Some("{}")).unwrap();
+        assert_eq!(std::fs::read_to_string(map_path(&path)).unwrap(), "{}");
+
+        write(&path, b"bytecode", "config", None).unwrap();
+        assert!(!map_path(&path).exists());
+    }
+}
@@ -0,0 +1,74 @@
+//! Errors raised to scripts as instances of their own classes.
+//!
+//! `Error::Exception` only carries a message, so a module wanting scripts to
+//! tell failures apart returns `Settled::Rejected` from its async functions
+//! instead. The error converts itself with `new_error`, and the promise is
+//! rejected with that object.
+//!
+//! Error objects are plain `Error`s named after their class, and the classes
+//! recognise instances by that name. Nothing needs to be kept around between
+//! calls, in the script or on the Rust side.
+
+use rquickjs::{Ctx, Function, IntoJs, Object, Result, Value};
+
+/// Constructor of the error class `name`. Instances extend `Error` and take
+/// their properties from the second argument. Any error named `name` counts
+/// as an instance, so every call returns an equivalent class
+pub fn error_class<'js>(ctx: Ctx<'js>, name: &str) -> Result<Function<'js>> {
+    ctx.eval(format!(
+        "(class {0} extends Error {{ constructor(message, props) {{ super(message); this.name = {0:?}; Object.assign(this, props); }} static [Symbol.hasInstance](value) {{ return value instanceof Error && value.name === {0:?}; }} }})",
+        name
+    ))
+}
+
+/// New instance of the error class `name`
+pub fn new_error<'js>(
+    ctx: Ctx<'js>,
+    name: &str,
+    message: impl Into<String>,
+    props: Object<'js>,
+) -> Result<Value<'js>> {
+    let globals = ctx.globals();
+
+    let error: Object = globals
+        .get::<_, Function>("Error")?
+        .construct((message.into(),))?;
+    error.set("name", name)?;
+
+    let assign: Function = globals.get::<_, Object>("Object")?.get("assign")?;
+    assign.call((error, props))
+}
+
+/// Outcome of an async function that rejects with a value of its own rather
+/// than an `Error`. A rejection converts to a rejected promise, which the
+/// promise of the function adopts
+pub enum Settled<T, E> {
+    Resolved(T),
+    Rejected(E),
+}
+
+impl<T, E> From<std::result::Result<T, E>> for Settled<T, E> {
+    fn from(ret: std::result::Result<T, E>) -> Self {
+        match ret {
+            Ok(value) => Settled::Resolved(value),
+            Err(err) => Settled::Rejected(err),
+        }
+    }
+}
+
+impl<'js, T, E> IntoJs<'js> for Settled<T, E>
+where
+    T: IntoJs<'js>,
+    E: IntoJs<'js>,
+{
+    fn into_js(self, ctx: Ctx<'js>) -> Result<Value<'js>> {
+        match self {
+            Settled::Resolved(value) => value.into_js(ctx),
+            Settled::Rejected(err) => {
+                let (promise, _, reject) = ctx.promise()?;
+                reject.call::<_, ()>((err.into_js(ctx)?,))?;
+                Ok(promise.into_value())
+            }
+        }
+    }
+}
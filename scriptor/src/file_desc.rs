@@ -1,21 +1,37 @@
-use futures_core::future::BoxFuture;
-use rquickjs::{Result, TypedArray};
-use std::{pin::Pin, sync::Arc};
+use futures_core::{future::BoxFuture, Stream};
+use rquickjs::TypedArray;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
 use tokio::{
     io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeek, AsyncWrite, AsyncWriteExt},
     sync::RwLock,
 };
+use tokio_stream::wrappers::LinesStream;
 
-use crate::stream::JsStream;
+use crate::{
+    error::Settled,
+    fs_error::{FsError, FsResult},
+    stream::JsStream,
+};
 
 pub struct FileDesc<F> {
     file: Arc<RwLock<F>>,
+    /// Path errors are reported for, empty for the standard streams
+    path: Arc<str>,
 }
 
 impl<F> FileDesc<F> {
     pub fn new(file: F) -> FileDesc<F> {
+        FileDesc::with_path(file, "")
+    }
+
+    pub fn with_path(file: F, path: impl Into<Arc<str>>) -> FileDesc<F> {
         FileDesc {
             file: Arc::new(RwLock::new(file)),
+            path: path.into(),
         }
     }
 }
@@ -24,31 +40,50 @@ impl<F> Clone for FileDesc<F> {
     fn clone(&self) -> Self {
         FileDesc {
             file: self.file.clone(),
+            path: self.path.clone(),
         }
     }
 }
 
+/// Lines of a file, failing with an `FsError`
+pub struct Lines<F> {
+    lines: LinesStream<tokio::io::BufReader<FileDesc<F>>>,
+    path: Arc<str>,
+}
+
+impl<F: AsyncRead + std::marker::Unpin> Stream for Lines<F> {
+    type Item = FsResult<String>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        Pin::new(&mut this.lines)
+            .poll_next(cx)
+            .map_err(|err| FsError::new(err, "read", &*this.path))
+    }
+}
+
 impl<F: AsyncRead + std::marker::Unpin + Send + 'static + Sync> FileDesc<F> {
-    pub fn read(&mut self) -> BoxFuture<'static, Result<Vec<u8>>> {
+    pub fn read(&mut self) -> BoxFuture<'static, Settled<Vec<u8>, FsError>> {
         let file = self.file.clone();
+        let path = self.path.clone();
         Box::pin(async move {
             let mut file = file.write().await;
             // let mut buf = Vec::with_capacity(1024);
             let mut buf: [u8; 1024] = [0; 1024];
-            let read = file.read(&mut buf[..]).await.map_err(throw!())?;
-            Ok(buf[0..read].to_vec())
+            file.read(&mut buf[..])
+                .await
+                .map(|read| buf[0..read].to_vec())
+                .map_err(|err| FsError::new(err, "read", &*path))
+                .into()
         })
     }
 
-    pub fn lines(
-        &self,
-    ) -> JsStream<tokio_stream::wrappers::LinesStream<tokio::io::BufReader<FileDesc<F>>>> {
-        let this = self.clone();
-        let file = tokio::io::BufReader::new(this);
+    pub fn lines(&self) -> JsStream<Lines<F>> {
+        let file = tokio::io::BufReader::new(self.clone());
 
-        let stream = tokio_stream::wrappers::LinesStream::new(file.lines());
-
-        JsStream::new(stream)
+        JsStream::new(Lines {
+            lines: LinesStream::new(file.lines()),
+            path: self.path.clone(),
+        })
     }
 }
 
@@ -67,30 +102,42 @@ impl<F: AsyncRead> AsyncRead for FileDesc<F> {
 }
 
 impl<F: AsyncWrite + std::marker::Unpin + Send + 'static + Sync> FileDesc<F> {
-    pub fn write(&mut self, data: TypedArray<'_, u8>) -> BoxFuture<'static, Result<()>> {
+    pub fn write(&mut self, data: TypedArray<'_, u8>) -> BoxFuture<'static, Settled<(), FsError>> {
         let file = self.file.clone();
+        let path = self.path.clone();
         let data: &[u8] = data.as_ref();
         let data = data.to_vec();
         Box::pin(async move {
             let mut file = file.write().await;
-            file.write_all(&data).await.map_err(throw!())
+            file.write_all(&data)
+                .await
+                .map_err(|err| FsError::new(err, "write", &*path))
+                .into()
         })
     }
 
-    pub fn write_str(&mut self, data: String) -> BoxFuture<'static, Result<()>> {
+    pub fn write_str(&mut self, data: String) -> BoxFuture<'static, Settled<(), FsError>> {
         let file = self.file.clone();
+        let path = self.path.clone();
 
         Box::pin(async move {
             let mut file = file.write().await;
-            file.write_all(data.as_bytes()).await.map_err(throw!())
+            file.write_all(data.as_bytes())
+                .await
+                .map_err(|err| FsError::new(err, "write", &*path))
+                .into()
         })
     }
 
-    pub fn flush(&mut self) -> BoxFuture<'static, Result<()>> {
+    pub fn flush(&mut self) -> BoxFuture<'static, Settled<(), FsError>> {
         let file = self.file.clone();
+        let path = self.path.clone();
         Box::pin(async move {
             let mut file = file.write().await;
-            file.flush().await.map_err(throw!())
+            file.flush()
+                .await
+                .map_err(|err| FsError::new(err, "flush", &*path))
+                .into()
         })
     }
 }
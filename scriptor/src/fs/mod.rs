@@ -1,26 +1,96 @@
 mod file;
+mod ops;
 mod read_dir;
+mod stat;
+mod walk;
+mod watch;
 
-use crate::{stream::JsStream, FileDesc};
-use rquickjs::{Async, Class, Func, ModuleDef, Result};
+use std::future::Future;
 
-use self::read_dir::{DirEntry, ReadDir};
+use crate::{error::Settled, stream::JsStream, FileDesc, Lines};
+use rquickjs::{Async, Class, Func, Function, ModuleDef, Opt};
+
+use self::{
+    ops::{MkdirOptions, RmOptions},
+    read_dir::{DirEntry, ReadDir},
+    stat::Stat,
+    walk::{Glob, GlobOptions, Walk, WalkOptions},
+    watch::{Paths, Watch, WatchOptions},
+};
+pub use crate::fs_error::{error_code, FsError, FsResult};
 
 pub struct Module;
 
-async fn read(path: String) -> Result<Vec<u8>> {
-    let output = tokio::fs::read(path).await.map_err(throw!())?;
-    Ok(output)
+/// Run `future`, rejecting with the `FsError` it fails with
+async fn settle<T>(future: impl Future<Output = FsResult<T>>) -> Settled<T, FsError> {
+    future.await.into()
+}
+
+async fn read(path: String) -> FsResult<Vec<u8>> {
+    tokio::fs::read(&path)
+        .await
+        .map_err(|err| FsError::new(err, "open", &path))
+}
+
+async fn write(path: String, data: Vec<u8>) -> FsResult<()> {
+    tokio::fs::write(&path, &data)
+        .await
+        .map_err(|err| FsError::new(err, "open", &path))
+}
+
+async fn stat(path: String) -> FsResult<Stat> {
+    let meta = tokio::fs::metadata(&path)
+        .await
+        .map_err(|err| FsError::new(err, "stat", &path))?;
+    Ok(Stat::from(meta))
+}
+
+async fn lstat(path: String) -> FsResult<Stat> {
+    let meta = tokio::fs::symlink_metadata(&path)
+        .await
+        .map_err(|err| FsError::new(err, "lstat", &path))?;
+    Ok(Stat::from(meta))
+}
+
+async fn write_str(path: String, data: String) -> FsResult<()> {
+    tokio::fs::write(&path, &data)
+        .await
+        .map_err(|err| FsError::new(err, "open", &path))
 }
 
-async fn write(path: String, data: Vec<u8>) -> Result<()> {
-    tokio::fs::write(path, &data).await.map_err(throw!())?;
-    Ok(())
+async fn open(path: String, mode: Opt<String>) -> FsResult<FileDesc<tokio::fs::File>> {
+    let mut opts = tokio::fs::OpenOptions::new();
+
+    match mode.0 {
+        Some(mode) => {
+            for ch in mode.chars() {
+                match ch {
+                    'r' => opts.read(true),
+                    'w' => opts.write(true),
+                    'a' => opts.append(true),
+                    't' => opts.truncate(true),
+                    'c' => opts.create(true),
+                    _ => &mut opts,
+                };
+            }
+        }
+        None => {
+            opts.read(true);
+        }
+    }
+
+    let file = opts
+        .open(&path)
+        .await
+        .map_err(|err| FsError::new(err, "open", &path))?;
+    Ok(FileDesc::with_path(file, path))
 }
 
-async fn write_str(path: String, data: String) -> Result<()> {
-    tokio::fs::write(path, &data).await.map_err(throw!())?;
-    Ok(())
+async fn read_dir(path: String) -> FsResult<JsStream<ReadDir>> {
+    let dir = tokio::fs::read_dir(&path)
+        .await
+        .map_err(|err| FsError::new(err, "scandir", &path))?;
+    Ok(JsStream::new(ReadDir { dir, path }))
 }
 
 impl ModuleDef for Module {
@@ -29,12 +99,30 @@ impl ModuleDef for Module {
         module: &rquickjs::Module<'js, rquickjs::Created>,
     ) -> rquickjs::Result<()> {
         module.add("File")?;
+        module.add("FsError")?;
 
         module.add("open")?;
         module.add("readFile")?;
         module.add("writeFile")?;
 
         module.add("readDir")?;
+        module.add("walk")?;
+        module.add("glob")?;
+        module.add("watch")?;
+
+        module.add("stat")?;
+        module.add("lstat")?;
+
+        module.add("mkdir")?;
+        module.add("rm")?;
+        module.add("rename")?;
+        module.add("copyFile")?;
+        module.add("copyDir")?;
+        module.add("symlink")?;
+        module.add("readlink")?;
+        module.add("chmod")?;
+        module.add("truncate")?;
+        module.add("exists")?;
 
         Ok(())
     }
@@ -44,62 +132,126 @@ impl ModuleDef for Module {
         module: &rquickjs::Module<'js, rquickjs::Loaded<rquickjs::Native>>,
     ) -> rquickjs::Result<()> {
         Class::<FileDesc<tokio::fs::File>>::register(ctx)?;
-        Class::<
-            JsStream<
-                tokio_stream::wrappers::LinesStream<
-                    tokio::io::BufReader<FileDesc<tokio::fs::File>>,
-                >,
-            >,
-        >::register(ctx)?;
+        Class::<JsStream<Lines<tokio::fs::File>>>::register(ctx)?;
 
         Class::<JsStream<ReadDir>>::register(ctx)?;
         Class::<DirEntry>::register(ctx)?;
+        Class::<JsStream<Walk>>::register(ctx)?;
+        Class::<JsStream<Glob>>::register(ctx)?;
+        Class::<JsStream<Watch>>::register(ctx)?;
+
+        module.set("FsError", crate::error::error_class(ctx, "FsError")?)?;
 
         module.set(
             "open",
             Func::new(
                 "open",
-                (
-                    Async(|path: String| async move {
-                        let file = tokio::fs::File::open(path).await.map_err(throw!())?;
-                        Result::<_>::Ok(FileDesc::new(file))
-                    }),
-                    Async(|path: String, mode: String| async move {
-                        let mut opts = tokio::fs::OpenOptions::new();
-
-                        for ch in mode.chars() {
-                            match ch {
-                                'r' => opts.read(true),
-                                'w' => opts.write(true),
-                                'a' => opts.append(true),
-                                't' => opts.truncate(true),
-                                'c' => opts.create(true),
-                                _ => &mut opts,
-                            };
-                        }
-
-                        let file = opts.open(path).await.map_err(throw!())?;
-                        Result::<_>::Ok(FileDesc::new(file))
-                    }),
-                ),
+                Async(|path: String, mode: Opt<String>| settle(open(path, mode))),
             ),
         )?;
 
-        module.set("writeFile", Func::from((Async(write), Async(write_str))))?;
+        module.set(
+            "writeFile",
+            Func::from((
+                Async(|path: String, data: Vec<u8>| settle(write(path, data))),
+                Async(|path: String, data: String| settle(write_str(path, data))),
+            )),
+        )?;
 
-        module.set("readFile", Func::from(Async(read)))?;
+        module.set(
+            "readFile",
+            Func::from(Async(|path: String| settle(read(path)))),
+        )?;
 
+        module.set("stat", Func::from(Async(|path: String| settle(stat(path)))))?;
         module.set(
-            "readDir",
-            Func::from(Async(|path: String| {
-                //
-                async move {
-                    Result::<_>::Ok(JsStream::new(ReadDir {
-                        dir: tokio::fs::read_dir(path).await.map_err(throw!())?,
-                    }))
-                }
+            "lstat",
+            Func::from(Async(|path: String| settle(lstat(path)))),
+        )?;
+
+        module.set(
+            "mkdir",
+            Func::from(Async(|path: String, options: Opt<MkdirOptions>| {
+                settle(ops::mkdir(path, options))
+            })),
+        )?;
+        module.set(
+            "rm",
+            Func::from(Async(|path: String, options: Opt<RmOptions>| {
+                settle(ops::rm(path, options))
             })),
         )?;
+        module.set(
+            "rename",
+            Func::from(Async(|from: String, to: String| {
+                settle(ops::rename(from, to))
+            })),
+        )?;
+        module.set(
+            "copyFile",
+            Func::from(Async(|from: String, to: String| {
+                settle(ops::copy_file(from, to))
+            })),
+        )?;
+        module.set(
+            "copyDir",
+            Func::from(Async(|from: String, to: String| {
+                settle(ops::copy_dir(from, to))
+            })),
+        )?;
+        module.set(
+            "symlink",
+            Func::from(Async(|target: String, path: String| {
+                settle(ops::symlink(target, path))
+            })),
+        )?;
+        module.set(
+            "readlink",
+            Func::from(Async(|path: String| settle(ops::readlink(path)))),
+        )?;
+        module.set(
+            "chmod",
+            Func::from(Async(|path: String, mode: u32| {
+                settle(ops::chmod(path, mode))
+            })),
+        )?;
+        module.set(
+            "truncate",
+            Func::from(Async(|path: String, len: Opt<u64>| {
+                settle(ops::truncate(path, len))
+            })),
+        )?;
+        module.set(
+            "exists",
+            Func::from(Async(|path: String| settle(ops::exists(path)))),
+        )?;
+
+        module.set(
+            "readDir",
+            Func::from(Async(|path: String| settle(read_dir(path)))),
+        )?;
+
+        let filter: Function = ctx.eval(walk::FILTER)?;
+        module.set(
+            "walk",
+            filter.call::<_, Function>((Func::from(
+                |path: String, options: Opt<WalkOptions>| JsStream::new(walk::walk(path, options)),
+            ),))?,
+        )?;
+
+        module.set(
+            "glob",
+            Func::from(|pattern: String, options: Opt<GlobOptions>| {
+                JsStream::new(walk::glob(pattern, options))
+            }),
+        )?;
+
+        module.set(
+            "watch",
+            Func::from(|paths: Paths, options: Opt<WatchOptions>| {
+                JsStream::new(watch::watch(paths, options))
+            }),
+        )?;
 
         Ok(())
     }
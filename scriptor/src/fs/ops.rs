@@ -0,0 +1,215 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use rquickjs::{Ctx, FromJs, Object, Opt, Result, Value};
+
+use crate::fs_error::{FsError, FsResult};
+
+#[derive(Default)]
+pub struct MkdirOptions {
+    recursive: bool,
+    mode: Option<u32>,
+}
+
+impl<'js> FromJs<'js> for MkdirOptions {
+    fn from_js(ctx: Ctx<'js>, value: Value<'js>) -> Result<Self> {
+        let obj = Object::from_js(ctx, value)?;
+        Ok(MkdirOptions {
+            recursive: obj.get::<_, Option<bool>>("recursive")?.unwrap_or_default(),
+            mode: obj.get("mode")?,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct RmOptions {
+    recursive: bool,
+    force: bool,
+}
+
+impl<'js> FromJs<'js> for RmOptions {
+    fn from_js(ctx: Ctx<'js>, value: Value<'js>) -> Result<Self> {
+        let obj = Object::from_js(ctx, value)?;
+        Ok(RmOptions {
+            recursive: obj.get::<_, Option<bool>>("recursive")?.unwrap_or_default(),
+            force: obj.get::<_, Option<bool>>("force")?.unwrap_or_default(),
+        })
+    }
+}
+
+pub async fn mkdir(path: String, options: Opt<MkdirOptions>) -> FsResult<()> {
+    let options = options.0.unwrap_or_default();
+
+    let mut builder = tokio::fs::DirBuilder::new();
+    builder.recursive(options.recursive);
+
+    #[cfg(unix)]
+    if let Some(mode) = options.mode {
+        builder.mode(mode);
+    }
+
+    builder
+        .create(&path)
+        .await
+        .map_err(|err| FsError::new(err, "mkdir", &path))
+}
+
+/// Remove a file, or a directory. Directories that are not empty are only
+/// removed with `recursive`, and `force` ignores paths that do not exist
+pub async fn rm(path: String, options: Opt<RmOptions>) -> FsResult<()> {
+    let options = options.0.unwrap_or_default();
+
+    let meta = match tokio::fs::symlink_metadata(&path).await {
+        Ok(meta) => meta,
+        Err(err) if options.force && err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(FsError::new(err, "lstat", &path)),
+    };
+
+    let ret = if !meta.is_dir() {
+        tokio::fs::remove_file(&path).await
+    } else if options.recursive {
+        tokio::fs::remove_dir_all(&path).await
+    } else {
+        tokio::fs::remove_dir(&path).await
+    };
+
+    ret.map_err(|err| FsError::new(err, "rm", &path))
+}
+
+pub async fn rename(from: String, to: String) -> FsResult<()> {
+    tokio::fs::rename(&from, &to)
+        .await
+        .map_err(|err| FsError::new(err, "rename", &from))
+}
+
+pub async fn copy_file(from: String, to: String) -> FsResult<()> {
+    tokio::fs::copy(&from, &to)
+        .await
+        .map(|_| ())
+        .map_err(|err| FsError::new(err, "copyfile", &from))
+}
+
+/// Copy the directory `from` and everything in it to `to`. Symlinks are
+/// copied as symlinks
+pub async fn copy_dir(from: String, to: String) -> FsResult<()> {
+    let mut queue = vec![(PathBuf::from(&from), PathBuf::from(&to))];
+
+    while let Some((from, to)) = queue.pop() {
+        tokio::fs::create_dir_all(&to)
+            .await
+            .map_err(FsError::with("mkdir", &to))?;
+
+        let mut dir = tokio::fs::read_dir(&from)
+            .await
+            .map_err(FsError::with("scandir", &from))?;
+
+        while let Some(entry) = dir
+            .next_entry()
+            .await
+            .map_err(FsError::with("scandir", &from))?
+        {
+            let source = entry.path();
+            let dest = to.join(entry.file_name());
+            let file_type = entry
+                .file_type()
+                .await
+                .map_err(FsError::with("lstat", &source))?;
+
+            if file_type.is_dir() {
+                queue.push((source, dest));
+            } else if file_type.is_symlink() {
+                let target = tokio::fs::read_link(&source)
+                    .await
+                    .map_err(FsError::with("readlink", &source))?;
+                create_symlink(&target, &dest)
+                    .await
+                    .map_err(FsError::with("symlink", &dest))?;
+            } else {
+                tokio::fs::copy(&source, &dest)
+                    .await
+                    .map_err(FsError::with("copyfile", &source))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn create_symlink(target: &Path, path: &Path) -> io::Result<()> {
+    tokio::fs::symlink(target, path).await
+}
+
+/// Windows has distinct links to files and directories
+#[cfg(windows)]
+async fn create_symlink(target: &Path, path: &Path) -> io::Result<()> {
+    let dir = path
+        .parent()
+        .map(|parent| parent.join(target).is_dir())
+        .unwrap_or_default();
+
+    if dir {
+        tokio::fs::symlink_dir(target, path).await
+    } else {
+        tokio::fs::symlink_file(target, path).await
+    }
+}
+
+/// Create a symlink at `path` pointing to `target`
+pub async fn symlink(target: String, path: String) -> FsResult<()> {
+    create_symlink(Path::new(&target), Path::new(&path))
+        .await
+        .map_err(|err| FsError::new(err, "symlink", &path))
+}
+
+pub async fn readlink(path: String) -> FsResult<String> {
+    let target = tokio::fs::read_link(&path)
+        .await
+        .map_err(|err| FsError::new(err, "readlink", &path))?;
+    Ok(target.to_string_lossy().to_string())
+}
+
+/// Set the permission bits of `path`. Only the write bit of the owner is
+/// used where there are no unix permissions
+pub async fn chmod(path: String, mode: u32) -> FsResult<()> {
+    let meta = tokio::fs::metadata(&path)
+        .await
+        .map_err(|err| FsError::new(err, "chmod", &path))?;
+    let mut permissions = meta.permissions();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(mode);
+    }
+
+    #[cfg(not(unix))]
+    permissions.set_readonly(mode & 0o200 == 0);
+
+    tokio::fs::set_permissions(&path, permissions)
+        .await
+        .map_err(|err| FsError::new(err, "chmod", &path))
+}
+
+/// Truncate or extend the file at `path` to `len` bytes, or empty it
+pub async fn truncate(path: String, len: Opt<u64>) -> FsResult<()> {
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .await
+        .map_err(|err| FsError::new(err, "open", &path))?;
+
+    file.set_len(len.0.unwrap_or_default())
+        .await
+        .map_err(|err| FsError::new(err, "ftruncate", &path))
+}
+
+pub async fn exists(path: String) -> FsResult<bool> {
+    match tokio::fs::symlink_metadata(&path).await {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(FsError::new(err, "lstat", &path)),
+    }
+}
@@ -1,10 +1,30 @@
-use std::task::Poll;
+use std::{sync::Arc, task::Poll};
 
 use futures_core::{ready, Stream};
-use rquickjs::{class_def, Accessor, Func, Method};
+use rquickjs::{class_def, Accessor, Async, Func, Method};
+
+use super::{settle, stat::Stat};
+use crate::fs_error::{FsError, FsResult};
 
 pub struct DirEntry {
-    entry: tokio::fs::DirEntry,
+    entry: Arc<tokio::fs::DirEntry>,
+}
+
+impl DirEntry {
+    /// `check` applied to the type of the entry, which does not follow symlinks
+    fn is(
+        &self,
+        check: fn(&std::fs::FileType) -> bool,
+    ) -> impl std::future::Future<Output = FsResult<bool>> {
+        let entry = self.entry.clone();
+        async move {
+            let file_type = entry
+                .file_type()
+                .await
+                .map_err(FsError::with("lstat", entry.path()))?;
+            Ok(check(&file_type))
+        }
+    }
 }
 
 class_def! {
@@ -15,6 +35,22 @@ class_def! {
             this.entry.path().as_os_str().to_string_lossy().to_string()
         })))?;
 
+        proto.prop("name", Accessor::from(Method(|this: &DirEntry| {
+            this.entry.file_name().to_string_lossy().to_string()
+        })))?;
+
+        proto.set("isFile", Func::from(Async(Method(|this: &DirEntry| settle(this.is(|m| m.is_file()))))))?;
+        proto.set("isDir", Func::from(Async(Method(|this: &DirEntry| settle(this.is(|m| m.is_dir()))))))?;
+        proto.set("isSymlink", Func::from(Async(Method(|this: &DirEntry| settle(this.is(|m| m.is_symlink()))))))?;
+
+        proto.set("stat", Func::from(Async(Method(|this: &DirEntry| {
+            let entry = this.entry.clone();
+            settle(async move {
+                let meta = entry.metadata().await.map_err(FsError::with("lstat", entry.path()))?;
+                Ok(Stat::from(meta))
+            })
+        }))))?;
+
     }
 }
 
@@ -22,11 +58,12 @@ pin_project_lite::pin_project! {
     pub struct ReadDir {
         #[pin]
         pub dir: tokio::fs::ReadDir,
+        pub path: String,
     }
 }
 
 impl Stream for ReadDir {
-    type Item = Result<DirEntry, rquickjs::Error>;
+    type Item = FsResult<DirEntry>;
     fn poll_next(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
@@ -34,9 +71,11 @@ impl Stream for ReadDir {
         let mut this = self.project();
 
         match ready!(this.dir.poll_next_entry(cx)) {
-            Ok(Some(entry)) => Poll::Ready(Some(Ok(DirEntry { entry }))),
+            Ok(Some(entry)) => Poll::Ready(Some(Ok(DirEntry {
+                entry: Arc::new(entry),
+            }))),
             Ok(None) => Poll::Ready(None),
-            Err(err) => Poll::Ready(Some(Err(throw!(err)))),
+            Err(err) => Poll::Ready(Some(Err(FsError::new(err, "scandir", &**this.path)))),
         }
     }
 }
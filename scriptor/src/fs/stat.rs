@@ -0,0 +1,89 @@
+use std::{
+    fs::{FileType, Metadata},
+    time::SystemTime,
+};
+
+use rquickjs::{Ctx, IntoJs, Object, Result, Value};
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+/// Metadata of a file, as returned by `stat`, `lstat` and `DirEntry.stat`
+pub struct Stat {
+    size: u64,
+    file_type: &'static str,
+    readonly: bool,
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    modified: Option<f64>,
+    accessed: Option<f64>,
+    created: Option<f64>,
+}
+
+/// Milliseconds since the unix epoch, as used by `Date`
+fn millis(time: std::io::Result<SystemTime>) -> Option<f64> {
+    let time = time.ok()?;
+    let ms = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since) => since.as_secs_f64() * 1000.0,
+        Err(err) => -err.duration().as_secs_f64() * 1000.0,
+    };
+    Some(ms)
+}
+
+/// Name of `file_type`, as in the `type` of a `Stat`
+pub fn type_name(file_type: &FileType) -> &'static str {
+    if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_file() {
+        "file"
+    } else {
+        "other"
+    }
+}
+
+impl From<Metadata> for Stat {
+    fn from(meta: Metadata) -> Stat {
+        let file_type = type_name(&meta.file_type());
+
+        #[cfg(unix)]
+        let (mode, uid, gid) = (Some(meta.mode()), Some(meta.uid()), Some(meta.gid()));
+        #[cfg(not(unix))]
+        let (mode, uid, gid) = (None, None, None);
+
+        Stat {
+            size: meta.len(),
+            file_type,
+            readonly: meta.permissions().readonly(),
+            mode,
+            uid,
+            gid,
+            modified: millis(meta.modified()),
+            accessed: millis(meta.accessed()),
+            created: millis(meta.created()),
+        }
+    }
+}
+
+impl<'js> IntoJs<'js> for Stat {
+    fn into_js(self, ctx: Ctx<'js>) -> Result<Value<'js>> {
+        let obj = Object::new(ctx)?;
+
+        obj.set("size", self.size as f64)?;
+        obj.set("type", self.file_type)?;
+        obj.set("isFile", self.file_type == "file")?;
+        obj.set("isDirectory", self.file_type == "directory")?;
+        obj.set("isSymlink", self.file_type == "symlink")?;
+        obj.set("readonly", self.readonly)?;
+        obj.set("mode", self.mode)?;
+        obj.set("uid", self.uid)?;
+        obj.set("gid", self.gid)?;
+        obj.set("modified", self.modified)?;
+        obj.set("accessed", self.accessed)?;
+        obj.set("created", self.created)?;
+
+        Ok(obj.into_value())
+    }
+}
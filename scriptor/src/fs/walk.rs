@@ -0,0 +1,332 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use globset::{Glob as Pattern, GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{DirEntry, WalkBuilder};
+use rquickjs::{Ctx, FromJs, Func, IntoJs, Object, Opt, Result, Value};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use super::stat::type_name;
+use crate::fs_error::{FsError, FsResult};
+
+/// Entries walked ahead of the script
+const BUFFER: usize = 64;
+
+/// Wraps the native `walk`, leaving out the entries `options.filter` rejects.
+/// The filter is a script function, so it can not run on the walking thread.
+/// Scripts get an async generator with or without a filter
+pub const FILTER: &str = "(walk) => (path, options) => {
+    const entries = walk(path, options);
+    const filter = options && options.filter;
+    return (async function* () {
+        for await (const entry of entries) {
+            if (!filter || (await filter(entry))) yield entry;
+        }
+    })();
+}";
+
+#[derive(Default)]
+pub struct WalkOptions {
+    max_depth: Option<u32>,
+    follow_symlinks: bool,
+    gitignore: bool,
+}
+
+impl<'js> FromJs<'js> for WalkOptions {
+    fn from_js(ctx: Ctx<'js>, value: Value<'js>) -> Result<Self> {
+        let obj = Object::from_js(ctx, value)?;
+        Ok(WalkOptions {
+            max_depth: obj.get("maxDepth")?,
+            follow_symlinks: obj
+                .get::<_, Option<bool>>("followSymlinks")?
+                .unwrap_or_default(),
+            gitignore: obj.get::<_, Option<bool>>("gitignore")?.unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct GlobOptions {
+    cwd: Option<String>,
+    ignore: Vec<String>,
+    follow_symlinks: bool,
+    gitignore: bool,
+}
+
+impl<'js> FromJs<'js> for GlobOptions {
+    fn from_js(ctx: Ctx<'js>, value: Value<'js>) -> Result<Self> {
+        let obj = Object::from_js(ctx, value)?;
+
+        let ignore = match obj.get::<_, Option<Value>>("ignore")? {
+            Some(value) if value.is_string() => vec![String::from_js(ctx, value)?],
+            Some(value) => Vec::from_js(ctx, value)?,
+            None => Vec::new(),
+        };
+
+        Ok(GlobOptions {
+            cwd: obj.get("cwd")?,
+            ignore,
+            follow_symlinks: obj
+                .get::<_, Option<bool>>("followSymlinks")?
+                .unwrap_or_default(),
+            gitignore: obj.get::<_, Option<bool>>("gitignore")?.unwrap_or_default(),
+        })
+    }
+}
+
+/// Entry found by `walk`
+pub struct WalkEntry {
+    path: String,
+    name: String,
+    depth: usize,
+    file_type: &'static str,
+}
+
+impl<'js> IntoJs<'js> for WalkEntry {
+    fn into_js(self, ctx: Ctx<'js>) -> Result<Value<'js>> {
+        let obj = Object::new(ctx)?;
+
+        obj.set("path", self.path)?;
+        obj.set("name", self.name)?;
+        obj.set("depth", self.depth as u32)?;
+        obj.set("type", self.file_type)?;
+        obj.set("isFile", self.file_type == "file")?;
+        obj.set("isDirectory", self.file_type == "directory")?;
+        obj.set("isSymlink", self.file_type == "symlink")?;
+
+        Ok(obj.into_value())
+    }
+}
+
+fn walk_error(err: ignore::Error) -> FsError {
+    fn error_path(err: &ignore::Error) -> Option<&Path> {
+        match err {
+            ignore::Error::WithPath { path, .. } => Some(path),
+            ignore::Error::Loop { child, .. } => Some(child),
+            ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+                error_path(err)
+            }
+            _ => None,
+        }
+    }
+
+    let path = error_path(&err)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let message = err.to_string();
+
+    let err = err
+        .into_io_error()
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::Other, message));
+    FsError::new(err, "scandir", path)
+}
+
+fn walker(root: &Path, follow_symlinks: bool, gitignore: bool) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .standard_filters(false)
+        .follow_links(follow_symlinks)
+        .git_ignore(gitignore)
+        .git_exclude(gitignore)
+        .require_git(false);
+    builder
+}
+
+fn pattern_error(err: globset::Error, pattern: &str) -> FsError {
+    FsError::new(
+        io::Error::new(io::ErrorKind::InvalidInput, err),
+        "glob",
+        pattern,
+    )
+}
+
+/// Stream failing with `err` on its first item
+fn failed<T>(err: FsError) -> ReceiverStream<FsResult<T>> {
+    let (sx, rx) = mpsc::channel(1);
+    let _ = sx.try_send(Err(err));
+    ReceiverStream::new(rx)
+}
+
+/// Run `builder` on a thread of its own, sending `map` of every entry below
+/// the root. Walking stops once the stream is dropped
+fn spawn<T, F>(builder: WalkBuilder, map: F) -> ReceiverStream<FsResult<T>>
+where
+    T: Send + 'static,
+    F: Fn(DirEntry) -> Option<T> + Send + 'static,
+{
+    let (sx, rx) = mpsc::channel(BUFFER);
+
+    std::thread::spawn(move || {
+        for next in builder.build() {
+            let item = match next {
+                Ok(entry) if entry.depth() == 0 => continue,
+                Ok(entry) => match map(entry) {
+                    Some(item) => Ok(item),
+                    None => continue,
+                },
+                Err(err) => Err(walk_error(err)),
+            };
+
+            if sx.blocking_send(item).is_err() {
+                break;
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+pub struct Walk(ReceiverStream<FsResult<WalkEntry>>);
+
+impl Stream for Walk {
+    type Item = FsResult<WalkEntry>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+stream!(Walk);
+
+/// Every file and directory below `path`, depth first
+pub fn walk(path: String, options: Opt<WalkOptions>) -> Walk {
+    let options = options.0.unwrap_or_default();
+
+    let mut builder = walker(Path::new(&path), options.follow_symlinks, options.gitignore);
+    builder.max_depth(options.max_depth.map(|depth| depth as usize));
+
+    Walk(spawn(builder, |entry| {
+        Some(WalkEntry {
+            path: entry.path().to_string_lossy().to_string(),
+            name: entry.file_name().to_string_lossy().to_string(),
+            depth: entry.depth(),
+            file_type: entry.file_type().map(|t| type_name(&t)).unwrap_or("other"),
+        })
+    }))
+}
+
+pub struct Glob(ReceiverStream<FsResult<String>>);
+
+impl Stream for Glob {
+    type Item = FsResult<String>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+stream!(Glob);
+
+fn is_glob(part: &str) -> bool {
+    part.contains(['*', '?', '[', '{'])
+}
+
+/// Leading directories of `pattern` without glob syntax, where walking can
+/// start, and the depth below it matches can be at
+fn split(pattern: &str) -> (PathBuf, Option<usize>) {
+    let parts = pattern.split('/').collect::<Vec<_>>();
+    let literal = parts[..parts.len() - 1]
+        .iter()
+        .take_while(|part| !is_glob(part))
+        .count();
+
+    let base = match parts[..literal].join("/") {
+        base if base.is_empty() && pattern.starts_with('/') => "/".to_string(),
+        base => base,
+    };
+
+    let rest = &parts[literal..];
+    let depth = if rest.iter().any(|part| part.contains("**")) {
+        None
+    } else {
+        Some(rest.len())
+    };
+
+    (PathBuf::from(base), depth)
+}
+
+/// Paths matching `pattern`, relative to `cwd` unless the pattern is absolute.
+/// Directories matching an `ignore` pattern are not descended into. A bad
+/// pattern fails the first item
+pub fn glob(pattern: String, options: Opt<GlobOptions>) -> Glob {
+    let options = options.0.unwrap_or_default();
+    let pattern = pattern.strip_prefix("./").unwrap_or(&pattern);
+
+    Glob(glob_matches(pattern, options).unwrap_or_else(failed))
+}
+
+fn glob_matches(pattern: &str, options: GlobOptions) -> FsResult<ReceiverStream<FsResult<String>>> {
+    let matcher = GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|err| pattern_error(err, pattern))?
+        .compile_matcher();
+
+    let mut ignore = GlobSetBuilder::new();
+    for pattern in &options.ignore {
+        ignore.add(Pattern::new(pattern).map_err(|err| pattern_error(err, pattern))?);
+    }
+    let ignore: GlobSet = ignore
+        .build()
+        .map_err(|err| pattern_error(err, &options.ignore.join(", ")))?;
+
+    let cwd = PathBuf::from(options.cwd.as_deref().unwrap_or("."));
+    let absolute = Path::new(pattern).is_absolute();
+    let (base, depth) = split(pattern);
+
+    let relative = {
+        let cwd = cwd.clone();
+        move |path: &Path| -> PathBuf {
+            if absolute {
+                path.to_path_buf()
+            } else {
+                path.strip_prefix(&cwd).unwrap_or(path).to_path_buf()
+            }
+        }
+    };
+
+    let mut builder = walker(&cwd.join(base), options.follow_symlinks, options.gitignore);
+    builder.max_depth(depth);
+
+    let filter = relative.clone();
+    builder.filter_entry(move |entry| entry.depth() == 0 || !ignore.is_match(filter(entry.path())));
+
+    Ok(spawn(builder, move |entry| {
+        let path = relative(entry.path());
+        if matcher.is_match(&path) {
+            Some(path.to_string_lossy().to_string())
+        } else {
+            None
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_literal_base() {
+        assert_eq!(split("src/lib/*.rs"), (PathBuf::from("src/lib"), Some(1)));
+        assert_eq!(split("src/*/mod.rs"), (PathBuf::from("src"), Some(2)));
+        assert_eq!(split("*.rs"), (PathBuf::from(""), Some(1)));
+        assert_eq!(split("/etc/*.conf"), (PathBuf::from("/etc"), Some(1)));
+        assert_eq!(split("/*"), (PathBuf::from("/"), Some(1)));
+    }
+
+    #[test]
+    fn recursive_patterns_have_no_depth() {
+        assert_eq!(split("src/**/*.rs"), (PathBuf::from("src"), None));
+        assert_eq!(split("**"), (PathBuf::from(""), None));
+    }
+
+    #[test]
+    fn bad_patterns_fail_with_einval() {
+        let err = glob_matches("src/[", GlobOptions::default()).unwrap_err();
+        assert!(err.to_string().starts_with("EINVAL: "), "{}", err);
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    io,
+    path::Path,
+    pin::Pin,
+    sync::mpsc as std_mpsc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures_core::Stream;
+use notify::{
+    event::{EventKind, ModifyKind},
+    Event, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use rquickjs::{Ctx, FromJs, Func, IntoJs, Object, Opt, Result, Value};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use crate::fs_error::{FsError, FsResult};
+
+/// A path, or a list of them
+pub struct Paths(Vec<String>);
+
+impl<'js> FromJs<'js> for Paths {
+    fn from_js(ctx: Ctx<'js>, value: Value<'js>) -> Result<Self> {
+        if value.is_string() {
+            Ok(Paths(vec![String::from_js(ctx, value)?]))
+        } else {
+            Ok(Paths(Vec::from_js(ctx, value)?))
+        }
+    }
+}
+
+pub struct WatchOptions {
+    recursive: bool,
+    debounce: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            recursive: false,
+            debounce: Duration::from_millis(100),
+        }
+    }
+}
+
+impl<'js> FromJs<'js> for WatchOptions {
+    fn from_js(ctx: Ctx<'js>, value: Value<'js>) -> Result<Self> {
+        let obj = Object::from_js(ctx, value)?;
+        let default = WatchOptions::default();
+
+        Ok(WatchOptions {
+            recursive: obj.get::<_, Option<bool>>("recursive")?.unwrap_or_default(),
+            debounce: obj
+                .get::<_, Option<u32>>("debounce")?
+                .map(|ms| Duration::from_millis(ms as u64))
+                .unwrap_or(default.debounce),
+        })
+    }
+}
+
+/// Change to one or more watched paths
+#[derive(Debug, PartialEq)]
+pub struct WatchEvent {
+    kind: &'static str,
+    paths: Vec<String>,
+}
+
+impl WatchEvent {
+    /// The event scripts are told about, if any. Access is not reported
+    fn from_event(event: Event) -> Option<WatchEvent> {
+        let kind = match event.kind {
+            EventKind::Create(_) => "create",
+            EventKind::Modify(ModifyKind::Name(_)) => "rename",
+            EventKind::Modify(_) | EventKind::Any => "modify",
+            EventKind::Remove(_) => "remove",
+            EventKind::Access(_) | EventKind::Other => return None,
+        };
+
+        Some(WatchEvent {
+            kind,
+            paths: event
+                .paths
+                .iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect(),
+        })
+    }
+}
+
+impl<'js> IntoJs<'js> for WatchEvent {
+    fn into_js(self, ctx: Ctx<'js>) -> Result<Value<'js>> {
+        let obj = Object::new(ctx)?;
+        obj.set("kind", self.kind)?;
+        obj.set("paths", self.paths)?;
+        Ok(obj.into_value())
+    }
+}
+
+fn io_error(err: notify::Error) -> io::Error {
+    let message = err.to_string();
+    match err.kind {
+        notify::ErrorKind::Io(err) => err,
+        notify::ErrorKind::PathNotFound => io::Error::new(io::ErrorKind::NotFound, message),
+        _ => io::Error::new(io::ErrorKind::Other, message),
+    }
+}
+
+fn watch_error(err: notify::Error) -> FsError {
+    let path = err
+        .paths
+        .first()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default();
+    FsError::new(io_error(err), "watch", path)
+}
+
+/// Debounce windows a batch of events can span. Paths changing without pause
+/// are still reported every so often
+const MAX_WINDOWS: u32 = 10;
+
+/// The batch starting with `first`, which ends once `next` has waited
+/// `debounce` without an event or `MAX_WINDOWS` times that has `elapsed` since
+/// the batch started. `next` waits at most the time it is given
+fn batch<T>(
+    first: T,
+    debounce: Duration,
+    mut next: impl FnMut(Duration) -> Option<T>,
+    elapsed: impl Fn() -> Duration,
+) -> Vec<T> {
+    let limit = debounce * MAX_WINDOWS;
+
+    let mut batch = vec![first];
+    loop {
+        let timeout = debounce.min(limit.saturating_sub(elapsed()));
+        if timeout.is_zero() {
+            break;
+        }
+
+        match next(timeout) {
+            Some(event) => batch.push(event),
+            None => break,
+        }
+    }
+
+    batch
+}
+
+/// Forward the events received from the watcher in batches (see `batch`).
+/// Repeats of an event within a batch are left out
+fn debounce(
+    rx: std_mpsc::Receiver<notify::Result<Event>>,
+    sx: mpsc::UnboundedSender<FsResult<WatchEvent>>,
+    debounce: Duration,
+) {
+    while let Ok(first) = rx.recv() {
+        let start = Instant::now();
+        let batch = batch(
+            first,
+            debounce,
+            |timeout| rx.recv_timeout(timeout).ok(),
+            || start.elapsed(),
+        );
+
+        let mut items: Vec<FsResult<WatchEvent>> = Vec::new();
+        for next in batch {
+            let item = match next {
+                Ok(event) => match WatchEvent::from_event(event) {
+                    Some(event) => Ok(event),
+                    None => continue,
+                },
+                Err(err) => Err(watch_error(err)),
+            };
+
+            if let Ok(event) = &item {
+                if items
+                    .iter()
+                    .any(|seen| matches!(seen, Ok(seen) if seen == event))
+                {
+                    continue;
+                }
+            }
+
+            items.push(item);
+        }
+
+        for item in items {
+            if sx.send(item).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Changes to the watched paths. Watching stops once it is dropped
+pub struct Watch {
+    events: UnboundedReceiverStream<FsResult<WatchEvent>>,
+    /// Missing when watching failed, and the error is the only event
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl Stream for Watch {
+    type Item = FsResult<WatchEvent>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.events).poll_next(cx)
+    }
+}
+
+stream!(Watch);
+
+/// Watch `paths` for changes, and the directories below them with `recursive`.
+/// Failing to watch them fails the first event
+pub fn watch(paths: Paths, options: Opt<WatchOptions>) -> Watch {
+    let (sx, rx) = mpsc::unbounded_channel();
+
+    let watcher = match start(paths, options.0.unwrap_or_default(), sx.clone()) {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            let _ = sx.send(Err(err));
+            None
+        }
+    };
+
+    Watch {
+        events: UnboundedReceiverStream::new(rx),
+        _watcher: watcher,
+    }
+}
+
+/// Watcher of `paths`, its events sent to `sx`
+fn start(
+    paths: Paths,
+    options: WatchOptions,
+    sx: mpsc::UnboundedSender<FsResult<WatchEvent>>,
+) -> FsResult<RecommendedWatcher> {
+    let first = paths.0.first().cloned().unwrap_or_default();
+
+    let (notify_sx, notify_rx) = std_mpsc::channel();
+    let mut watcher = notify::recommended_watcher(notify_sx)
+        .map_err(|err| FsError::new(io_error(err), "watch", &first))?;
+
+    let mode = if options.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+
+    for path in &paths.0 {
+        watcher
+            .watch(Path::new(path), mode)
+            .map_err(|err| FsError::new(io_error(err), "watch", path))?;
+    }
+
+    std::thread::spawn(move || debounce(notify_rx, sx, options.debounce));
+
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{AccessKind, CreateKind};
+
+    use super::*;
+
+    fn created(path: &str) -> notify::Result<Event> {
+        Ok(Event::new(EventKind::Create(CreateKind::File)).add_path(path.into()))
+    }
+
+    #[test]
+    fn leaves_out_repeats_and_access() {
+        let (notify_sx, notify_rx) = std_mpsc::channel();
+        notify_sx.send(created("a")).unwrap();
+        notify_sx
+            .send(Ok(Event::new(EventKind::Access(AccessKind::Any))))
+            .unwrap();
+        notify_sx.send(created("a")).unwrap();
+        notify_sx.send(created("b")).unwrap();
+        drop(notify_sx);
+
+        let (sx, mut rx) = mpsc::unbounded_channel();
+        debounce(notify_rx, sx, Duration::from_millis(10));
+
+        let mut paths = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            let event = event.unwrap();
+            assert_eq!(event.kind, "create");
+            paths.extend(event.paths);
+        }
+        assert_eq!(paths, ["a", "b"]);
+    }
+
+    #[test]
+    fn ends_batches_after_a_quiet_window() {
+        let window = Duration::from_millis(20);
+        let mut timeouts = Vec::new();
+
+        let batch = batch(
+            0,
+            window,
+            |timeout| {
+                timeouts.push(timeout);
+                None
+            },
+            || Duration::ZERO,
+        );
+
+        assert_eq!(batch, [0]);
+        assert_eq!(timeouts, [window]);
+    }
+
+    #[test]
+    fn flushes_while_events_keep_arriving() {
+        // An event arrives every quarter of a window, forever
+        let window = Duration::from_millis(20);
+        let clock = std::cell::Cell::new(Duration::ZERO);
+
+        let batch = batch(
+            Duration::ZERO,
+            window,
+            |timeout| {
+                assert!(timeout <= window);
+                clock.set(clock.get() + window / 4);
+                Some(clock.get())
+            },
+            || clock.get(),
+        );
+
+        assert_eq!(batch.len() as u32, 1 + 4 * MAX_WINDOWS);
+        assert_eq!(clock.get(), window * MAX_WINDOWS);
+    }
+
+    #[tokio::test]
+    async fn failing_to_watch_fails_the_only_event() {
+        use tokio_stream::StreamExt;
+
+        let mut watch = watch(
+            Paths(vec!["/scriptor-watch-missing".to_string()]),
+            Opt(None),
+        );
+
+        let err = watch.next().await.unwrap().unwrap_err();
+        assert!(err.to_string().starts_with("ENOENT: "), "{}", err);
+        assert!(watch.next().await.is_none());
+    }
+}
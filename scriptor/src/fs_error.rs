@@ -0,0 +1,168 @@
+//! Io failures raised to scripts as `FsError`s, by the fs module and by the
+//! file descriptors `os` shares with it
+
+use std::{fmt, io, path::Path};
+
+use rquickjs::{Ctx, IntoJs, Object, Result, Value};
+
+use crate::error::new_error;
+
+/// Errno style code of `err`, like `ENOENT` or `EACCES`
+pub fn error_code(err: &io::Error) -> &'static str {
+    #[cfg(unix)]
+    if let Some(code) = err.raw_os_error().and_then(errno_code) {
+        return code;
+    }
+
+    match err.kind() {
+        io::ErrorKind::NotFound => "ENOENT",
+        io::ErrorKind::PermissionDenied => "EACCES",
+        io::ErrorKind::AlreadyExists => "EEXIST",
+        io::ErrorKind::InvalidInput => "EINVAL",
+        io::ErrorKind::Interrupted => "EINTR",
+        io::ErrorKind::WouldBlock => "EAGAIN",
+        io::ErrorKind::BrokenPipe => "EPIPE",
+        io::ErrorKind::TimedOut => "ETIMEDOUT",
+        io::ErrorKind::Unsupported => "ENOTSUP",
+        io::ErrorKind::OutOfMemory => "ENOMEM",
+        _ => "EIO",
+    }
+}
+
+#[cfg(unix)]
+fn errno_code(errno: i32) -> Option<&'static str> {
+    let code = match errno {
+        1 => "EPERM",
+        2 => "ENOENT",
+        5 => "EIO",
+        9 => "EBADF",
+        13 => "EACCES",
+        16 => "EBUSY",
+        17 => "EEXIST",
+        18 => "EXDEV",
+        20 => "ENOTDIR",
+        21 => "EISDIR",
+        22 => "EINVAL",
+        24 => "EMFILE",
+        27 => "EFBIG",
+        28 => "ENOSPC",
+        30 => "EROFS",
+        #[cfg(target_os = "linux")]
+        36 => "ENAMETOOLONG",
+        #[cfg(target_os = "linux")]
+        39 => "ENOTEMPTY",
+        #[cfg(target_os = "linux")]
+        40 => "ELOOP",
+        #[cfg(target_os = "macos")]
+        62 => "ELOOP",
+        #[cfg(target_os = "macos")]
+        63 => "ENAMETOOLONG",
+        #[cfg(target_os = "macos")]
+        66 => "ENOTEMPTY",
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Failure of a filesystem operation, raised to scripts as an `FsError` with
+/// `code`, `path`, `syscall` and `errno` properties
+#[derive(Debug)]
+pub struct FsError {
+    code: &'static str,
+    message: String,
+    path: String,
+    syscall: &'static str,
+    errno: Option<i32>,
+}
+
+pub type FsResult<T> = std::result::Result<T, FsError>;
+
+impl FsError {
+    /// `err`, raised by `syscall` on `path`. The message starts with the error
+    /// code, as in `ENOENT: No such file or directory, open 'x'`. An empty
+    /// `path`, as for stdin, is left out of it
+    pub fn new(err: io::Error, syscall: &'static str, path: impl Into<String>) -> FsError {
+        let path = path.into();
+        let code = error_code(&err);
+
+        let message = if path.is_empty() {
+            format!("{}: {}, {}", code, err, syscall)
+        } else {
+            format!("{}: {}, {} '{}'", code, err, syscall, path)
+        };
+
+        FsError {
+            code,
+            message,
+            path,
+            syscall,
+            errno: err.raw_os_error(),
+        }
+    }
+
+    /// Closure turning an io error into an `FsError`, for `map_err`
+    pub fn with(
+        syscall: &'static str,
+        path: impl AsRef<Path>,
+    ) -> impl FnOnce(io::Error) -> FsError {
+        let path = path.as_ref().to_string_lossy().to_string();
+        move |err| FsError::new(err, syscall, path)
+    }
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl<'js> IntoJs<'js> for FsError {
+    fn into_js(self, ctx: Ctx<'js>) -> Result<Value<'js>> {
+        let props = Object::new(ctx)?;
+        props.set("code", self.code)?;
+        props.set("path", self.path)?;
+        props.set("syscall", self.syscall)?;
+        props.set("errno", self.errno)?;
+
+        new_error(ctx, "FsError", self.message, props)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_from_kind() {
+        let err = io::Error::new(io::ErrorKind::NotFound, "gone");
+        assert_eq!(error_code(&err), "ENOENT");
+
+        let err = io::Error::new(io::ErrorKind::Other, "odd");
+        assert_eq!(error_code(&err), "EIO");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn codes_from_errno() {
+        assert_eq!(error_code(&io::Error::from_raw_os_error(20)), "ENOTDIR");
+        assert_eq!(error_code(&io::Error::from_raw_os_error(21)), "EISDIR");
+    }
+
+    #[test]
+    fn message_names_syscall_and_path() {
+        let err = FsError::new(
+            io::Error::new(io::ErrorKind::NotFound, "gone"),
+            "open",
+            "a.txt",
+        );
+        assert_eq!(err.to_string(), "ENOENT: gone, open 'a.txt'");
+        assert_eq!(err.errno, None);
+
+        let err = FsError::new(
+            io::Error::new(io::ErrorKind::BrokenPipe, "closed"),
+            "write",
+            "",
+        );
+        assert_eq!(err.to_string(), "EPIPE: closed, write");
+    }
+}
@@ -0,0 +1,319 @@
+//! Import maps, as described in <https://github.com/WICG/import-maps>.
+//!
+//! Keys ending in `/` remap every specifier starting with them. Targets
+//! starting with `./`, `../` or `/` are paths relative to the import map,
+//! anything else is resolved again as a bare specifier.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use rquickjs::{Ctx, Resolver, Result};
+use serde::Deserialize;
+
+use crate::utils::normalize_path;
+
+/// Name of the import map picked up from the working directory
+pub const IMPORT_MAP: &str = "import_map.json";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ImportMap {
+    #[serde(default)]
+    imports: BTreeMap<String, String>,
+    #[serde(default)]
+    scopes: BTreeMap<String, BTreeMap<String, String>>,
+    /// Directory relative targets are resolved against
+    #[serde(skip)]
+    base: PathBuf,
+}
+
+impl ImportMap {
+    pub fn new(base: impl Into<PathBuf>) -> ImportMap {
+        ImportMap {
+            base: base.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Parse an import map, resolving relative targets against `base`
+    pub fn from_json(json: &str, base: impl Into<PathBuf>) -> io::Result<ImportMap> {
+        let mut map: ImportMap = serde_json::from_str(json)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        map.base = base.into();
+        Ok(map)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<ImportMap> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)?;
+        let base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        ImportMap::from_json(&json, base).map_err(|err| {
+            io::Error::new(err.kind(), format!("{}: {}", path.to_string_lossy(), err))
+        })
+    }
+
+    /// Map `specifier` to `target`. Keys ending in `/` map every specifier with that prefix
+    pub fn insert(&mut self, specifier: impl Into<String>, target: impl Into<String>) -> &mut Self {
+        self.imports.insert(specifier.into(), target.into());
+        self
+    }
+
+    /// Add the entries of `other`, replacing those already present. Relative
+    /// targets of a map without a base are resolved against this map's base
+    pub fn extend(&mut self, other: ImportMap) -> &mut Self {
+        let base = other.base;
+        let absolute = |target: String| {
+            if is_path(&target) && !base.as_os_str().is_empty() {
+                normalize_path(&base.join(target))
+                    .to_string_lossy()
+                    .to_string()
+            } else {
+                target
+            }
+        };
+
+        for (key, target) in other.imports {
+            self.imports.insert(key, absolute(target));
+        }
+
+        for (scope, imports) in other.scopes {
+            let scope = absolute(scope);
+            let entry = self.scopes.entry(scope).or_default();
+            for (key, target) in imports {
+                entry.insert(key, absolute(target));
+            }
+        }
+
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.imports.is_empty() && self.scopes.is_empty()
+    }
+
+    /// Look `name`, imported from `base`, up in the map
+    pub fn lookup(&self, base: &str, name: &str) -> Option<Mapped> {
+        let base = self.absolute(base);
+
+        // The most specific scope containing the importer goes first
+        let scopes = self
+            .scopes
+            .iter()
+            .filter(|(scope, _)| base.starts_with(&self.absolute(scope)))
+            .max_by_key(|(scope, _)| scope.len())
+            .map(|(_, imports)| imports);
+
+        let target = scopes
+            .and_then(|imports| lookup(imports, name))
+            .or_else(|| lookup(&self.imports, name))?;
+
+        Some(if is_path(&target) {
+            Mapped::Path(normalize_path(&self.base.join(target)))
+        } else {
+            Mapped::Specifier(target)
+        })
+    }
+
+    fn absolute(&self, path: &str) -> String {
+        if is_path(path) {
+            normalize_path(&self.base.join(path))
+                .to_string_lossy()
+                .to_string()
+        } else {
+            path.to_string()
+        }
+    }
+}
+
+/// Result of looking a specifier up in an import map
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mapped {
+    Path(PathBuf),
+    /// Another specifier, to be resolved in its place
+    Specifier(String),
+}
+
+fn is_path(name: &str) -> bool {
+    name.starts_with("./") || name.starts_with("../") || name.starts_with('/')
+}
+
+/// Exact match, or else the longest matching prefix key
+fn lookup(imports: &BTreeMap<String, String>, name: &str) -> Option<String> {
+    if let Some(target) = imports.get(name) {
+        return Some(target.clone());
+    }
+
+    imports
+        .iter()
+        .filter(|(key, _)| key.ends_with('/') && name.starts_with(key.as_str()))
+        .max_by_key(|(key, _)| key.len())
+        .map(|(key, target)| format!("{}{}", target, &name[key.len()..]))
+}
+
+/// Applies an import map before handing specifiers to `inner`
+#[derive(Clone, Debug)]
+pub struct ImportMapResolver<R> {
+    map: ImportMap,
+    inner: R,
+    extensions: Vec<String>,
+}
+
+impl<R> ImportMapResolver<R> {
+    pub fn new(map: ImportMap, inner: R) -> ImportMapResolver<R> {
+        ImportMapResolver {
+            map,
+            inner,
+            extensions: vec!["js".to_string(), "mjs".to_string()],
+        }
+    }
+
+    /// Try `ext` on mapped paths that do not exist as is
+    pub fn add_extension(&mut self, ext: impl Into<String>) -> &mut Self {
+        self.extensions.push(ext.into());
+        self
+    }
+
+    /// The same map and extensions in front of another resolver
+    pub fn wrap<T>(self, inner: T) -> ImportMapResolver<T> {
+        ImportMapResolver {
+            map: self.map,
+            inner,
+            extensions: self.extensions,
+        }
+    }
+
+    /// `path`, or the first existing file with one of the extensions added,
+    /// directly or to its `index`
+    fn probe(&self, path: PathBuf) -> PathBuf {
+        if path.is_file() {
+            return path;
+        }
+
+        let with_extension = |path: &Path| {
+            self.extensions.iter().find_map(|ext| {
+                let mut file = path.as_os_str().to_owned();
+                file.push(".");
+                file.push(ext);
+                let file = PathBuf::from(file);
+                if file.is_file() {
+                    Some(file)
+                } else {
+                    None
+                }
+            })
+        };
+
+        with_extension(&path)
+            .or_else(|| with_extension(&path.join("index")))
+            .unwrap_or(path)
+    }
+}
+
+impl<R: Resolver> Resolver for ImportMapResolver<R> {
+    fn resolve<'js>(&mut self, ctx: Ctx<'js>, base: &str, name: &str) -> Result<String> {
+        match self.map.lookup(base, name) {
+            Some(Mapped::Path(path)) => Ok(self.probe(path).to_string_lossy().to_string()),
+            Some(Mapped::Specifier(specifier)) => self.inner.resolve(ctx, base, &specifier),
+            None => self.inner.resolve(ctx, base, name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(json: &str) -> ImportMap {
+        ImportMap::from_json(json, "/project").unwrap()
+    }
+
+    #[test]
+    fn maps_exact_and_prefix_keys() {
+        let map = map(r#"{
+            "imports": {
+                "lodash": "./vendor/lodash.js",
+                "lib/": "./src/lib/",
+                "lib/deep/": "./deep/",
+                "react": "preact"
+            }
+        }"#);
+
+        assert_eq!(
+            map.lookup("/project/main.js", "lodash"),
+            Some(Mapped::Path(PathBuf::from("/project/vendor/lodash.js")))
+        );
+        assert_eq!(
+            map.lookup("/project/main.js", "lib/a.js"),
+            Some(Mapped::Path(PathBuf::from("/project/src/lib/a.js")))
+        );
+        assert_eq!(
+            map.lookup("/project/main.js", "lib/deep/b.js"),
+            Some(Mapped::Path(PathBuf::from("/project/deep/b.js")))
+        );
+        assert_eq!(
+            map.lookup("/project/main.js", "react"),
+            Some(Mapped::Specifier("preact".to_string()))
+        );
+        assert_eq!(map.lookup("/project/main.js", "vue"), None);
+    }
+
+    #[test]
+    fn most_specific_scope_wins() {
+        let map = map(r#"{
+            "imports": { "dep": "./dep.js" },
+            "scopes": {
+                "./legacy/": { "dep": "./dep-v1.js" },
+                "./legacy/old/": { "dep": "./dep-v0.js" }
+            }
+        }"#);
+
+        assert_eq!(
+            map.lookup("/project/main.js", "dep"),
+            Some(Mapped::Path(PathBuf::from("/project/dep.js")))
+        );
+        assert_eq!(
+            map.lookup("/project/legacy/a.js", "dep"),
+            Some(Mapped::Path(PathBuf::from("/project/dep-v1.js")))
+        );
+        assert_eq!(
+            map.lookup("/project/legacy/old/a.js", "dep"),
+            Some(Mapped::Path(PathBuf::from("/project/dep-v0.js")))
+        );
+    }
+
+    #[test]
+    fn extends_with_paths_of_the_other_base() {
+        let mut map = ImportMap::new("/project");
+        map.insert("dep", "./dep.js").insert("other", "./other.js");
+
+        let mut aliases = ImportMap::new("/shared");
+        aliases.insert("dep", "./vendor/dep.js");
+        map.extend(aliases);
+
+        // Entries without a base stay relative to the extended map
+        let mut plain = ImportMap::default();
+        plain.insert("@/", "./src/");
+        map.extend(plain);
+
+        assert_eq!(
+            map.lookup("/project/main.js", "dep"),
+            Some(Mapped::Path(PathBuf::from("/shared/vendor/dep.js")))
+        );
+        assert_eq!(
+            map.lookup("/project/main.js", "other"),
+            Some(Mapped::Path(PathBuf::from("/project/other.js")))
+        );
+        assert_eq!(
+            map.lookup("/project/main.js", "@/util.js"),
+            Some(Mapped::Path(PathBuf::from("/project/src/util.js")))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        let err = ImportMap::from_json("{", "/project").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
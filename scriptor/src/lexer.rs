@@ -0,0 +1,264 @@
+//! Just enough of a javascript tokenizer to find imports: comments and
+//! whitespace are skipped, and strings, templates and regex literals are
+//! single tokens so nothing inside them is mistaken for code
+
+/// Words after which a `/` starts a regex rather than a division
+const REGEX_KEYWORDS: &[&str] = &[
+    "return",
+    "typeof",
+    "instanceof",
+    "in",
+    "of",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "case",
+    "do",
+    "else",
+    "yield",
+    "await",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum TokenKind {
+    /// Identifier, keyword or number
+    Word,
+    String,
+    Template,
+    Regex,
+    Punct,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Token<'a> {
+    pub(crate) kind: TokenKind,
+    pub(crate) text: &'a str,
+    /// Byte offset of the token in the source
+    pub(crate) start: usize,
+    /// False for a string, template or regex literal missing its closing
+    /// delimiter
+    pub(crate) terminated: bool,
+}
+
+impl<'a> Token<'a> {
+    pub(crate) fn end(&self) -> usize {
+        self.start + self.text.len()
+    }
+
+    pub(crate) fn is_word(&self, word: &str) -> bool {
+        self.kind == TokenKind::Word && self.text == word
+    }
+
+    pub(crate) fn is_punct(&self, punct: char) -> bool {
+        self.kind == TokenKind::Punct && self.text.starts_with(punct)
+    }
+
+    /// Content of a string literal, escapes left as they are
+    pub(crate) fn string_value(&self) -> Option<&'a str> {
+        if self.kind != TokenKind::String || self.text.len() < 2 {
+            return None;
+        }
+        Some(&self.text[1..self.text.len() - 1])
+    }
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '$'
+}
+
+pub(crate) struct Tokens<'a> {
+    source: &'a str,
+    pos: usize,
+    prev: Option<Token<'a>>,
+    open_comment: bool,
+}
+
+impl<'a> Tokens<'a> {
+    pub(crate) fn new(source: &'a str) -> Tokens<'a> {
+        Tokens {
+            source,
+            pos: 0,
+            prev: None,
+            open_comment: false,
+        }
+    }
+
+    /// Whether the source ended inside a block comment
+    pub(crate) fn in_comment(&self) -> bool {
+        self.open_comment
+    }
+
+    /// Whether a `/` here starts a regex, judging by the token before it
+    fn regex_allowed(&self) -> bool {
+        match self.prev {
+            None => true,
+            Some(token) => match token.kind {
+                TokenKind::Word => REGEX_KEYWORDS.contains(&token.text),
+                TokenKind::Punct => !matches!(token.text, ")" | "]" | "}"),
+                _ => false,
+            },
+        }
+    }
+
+    /// Length of the string or template literal at the start of `rest`, and
+    /// whether it is terminated. An unterminated string ends at the end of
+    /// the line
+    fn quoted_len(rest: &str, quote: char) -> (usize, bool) {
+        let mut chars = rest.char_indices().skip(1);
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '\\' => {
+                    chars.next();
+                }
+                '\n' if quote != '`' => return (i, false),
+                c if c == quote => return (i + 1, true),
+                _ => {}
+            }
+        }
+        (rest.len(), false)
+    }
+
+    /// Length of the regex literal at the start of `rest`, flags included,
+    /// and whether it is terminated. A `/` inside a character class does not
+    /// end it
+    fn regex_len(rest: &str) -> (usize, bool) {
+        let mut class = false;
+        let mut chars = rest.char_indices().skip(1);
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '\\' => {
+                    chars.next();
+                }
+                '[' => class = true,
+                ']' => class = false,
+                '/' if !class => {
+                    let flags = rest[i + 1..]
+                        .find(|c| !is_word_char(c))
+                        .unwrap_or(rest.len() - i - 1);
+                    return (i + 1 + flags, true);
+                }
+                '\n' => return (i, false),
+                _ => {}
+            }
+        }
+        (rest.len(), false)
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        loop {
+            let rest = &self.source[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+
+            let rest = trimmed;
+            let ch = rest.chars().next()?;
+
+            let (kind, (len, terminated)) = if rest.starts_with("//") {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+                continue;
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                match comment.find("*/") {
+                    Some(n) => self.pos += n + 4,
+                    None => {
+                        self.pos += rest.len();
+                        self.open_comment = true;
+                    }
+                }
+                continue;
+            } else {
+                match ch {
+                    '"' | '\'' => (TokenKind::String, Self::quoted_len(rest, ch)),
+                    '`' => (TokenKind::Template, Self::quoted_len(rest, ch)),
+                    '/' if self.regex_allowed() => (TokenKind::Regex, Self::regex_len(rest)),
+                    c if is_word_char(c) => (
+                        TokenKind::Word,
+                        (rest.find(|c| !is_word_char(c)).unwrap_or(rest.len()), true),
+                    ),
+                    c => (TokenKind::Punct, (c.len_utf8(), true)),
+                }
+            };
+
+            let token = Token {
+                kind,
+                text: &rest[..len],
+                start: self.pos,
+                terminated,
+            };
+
+            self.pos += len;
+            self.prev = Some(token);
+
+            return Some(token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<(TokenKind, &str)> {
+        Tokens::new(source).map(|t| (t.kind, t.text)).collect()
+    }
+
+    #[test]
+    fn skips_comments() {
+        assert_eq!(
+            kinds("a // import 'x'\n/* from 'y' */ b"),
+            [(TokenKind::Word, "a"), (TokenKind::Word, "b")]
+        );
+    }
+
+    #[test]
+    fn keeps_strings_whole() {
+        assert_eq!(
+            kinds(r#"x = "a \" // b" + `c`"#),
+            [
+                (TokenKind::Word, "x"),
+                (TokenKind::Punct, "="),
+                (TokenKind::String, r#""a \" // b""#),
+                (TokenKind::Punct, "+"),
+                (TokenKind::Template, "`c`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tells_regexes_from_divisions() {
+        assert_eq!(
+            kinds("a = /'[/]/g; b = c / d / e"),
+            [
+                (TokenKind::Word, "a"),
+                (TokenKind::Punct, "="),
+                (TokenKind::Regex, "/'[/]/g"),
+                (TokenKind::Punct, ";"),
+                (TokenKind::Word, "b"),
+                (TokenKind::Punct, "="),
+                (TokenKind::Word, "c"),
+                (TokenKind::Punct, "/"),
+                (TokenKind::Word, "d"),
+                (TokenKind::Punct, "/"),
+                (TokenKind::Word, "e"),
+            ]
+        );
+        assert_eq!(kinds("return /x/")[1], (TokenKind::Regex, "/x/"));
+    }
+
+    #[test]
+    fn reports_unterminated_literals() {
+        let last = |source| Tokens::new(source).last().unwrap().terminated;
+        assert!(last("'a'"));
+        assert!(!last("'a"));
+        assert!(!last("`a ${b}\n"));
+        assert!(!last("x = /a"));
+
+        let mut tokens = Tokens::new("a /* b");
+        assert_eq!(tokens.by_ref().count(), 1);
+        assert!(tokens.in_comment());
+    }
+}
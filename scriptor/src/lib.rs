@@ -3,29 +3,49 @@ mod macros;
 
 #[cfg(feature = "vm")]
 mod vm;
+#[cfg(feature = "vm")]
+mod watch;
+
+#[cfg(all(feature = "vm", feature = "os"))]
+pub mod repl;
 
 // mod builder;
 #[cfg(any(feature = "fs", feature = "os"))]
 mod file_desc;
+#[cfg(any(feature = "fs", feature = "os"))]
+mod fs_error;
 pub mod global;
 mod process;
 #[cfg(any(feature = "fs", feature = "os"))]
 mod stream;
 
 mod bundle;
+mod error;
 mod ext;
 mod utils;
 
+mod assets;
 mod bundle_module;
+#[cfg(feature = "vm")]
+mod bundler;
+#[cfg(feature = "vm")]
+mod bytecode;
+mod import_map;
+mod lexer;
+mod node_resolver;
+#[cfg(all(feature = "vm", feature = "http"))]
+mod remote;
+mod source_map;
+mod transpile_cache;
 mod user_module;
 
-// #[cfg(feature = "typescript")]
-// mod typescript_loader;
+#[cfg(feature = "typescript")]
+mod typescript_loader;
 
 use rquickjs::{BuiltinResolver, Loader, ModuleLoader, Resolver};
 
-// #[cfg(feature = "typescript")]
-// pub use typescript_loader::*;
+#[cfg(feature = "typescript")]
+pub use typescript_loader::*;
 
 #[cfg(feature = "http")]
 pub mod http;
@@ -41,12 +61,30 @@ pub mod os;
 pub use bundle::{PIPE, TASKS, UTIL};
 
 #[cfg(feature = "vm")]
-pub use vm::{Vm, VmBuilder};
+pub use bundler::{BundleFormat, ScriptBundle, BUNDLE_EXTENSION};
+#[cfg(feature = "vm")]
+pub use bytecode::{bytecode_path, BytecodeLoader, BYTECODE_EXTENSION};
+#[cfg(feature = "vm")]
+pub use vm::{DirConfig, Vm, VmBuilder};
+#[cfg(feature = "vm")]
+pub use watch::{watch_main, LoadedFiles, WatchOptions};
+
+#[cfg(all(feature = "vm", feature = "os"))]
+pub use repl::Repl;
 
 pub use rquickjs::{Error, Result};
 
+pub use error::{error_class, new_error, Settled};
+
 pub use user_module::{IntoUserModule, UserModule, UserModuleImpl};
 
+pub use import_map::{ImportMap, ImportMapResolver, Mapped, IMPORT_MAP};
+pub use node_resolver::NodeResolver;
+#[cfg(all(feature = "vm", feature = "http"))]
+pub use remote::{Lockfile, RemoteModules, LOCKFILE};
+pub use source_map::SourceMaps;
+pub use transpile_cache::{TranspileCache, Transpiled};
+
 #[cfg(any(feature = "fs", feature = "os"))]
 pub(crate) use file_desc::*;
 #[cfg(any(feature = "fs", feature = "os"))]
@@ -56,6 +94,11 @@ pub use ext::*;
 
 #[cfg(feature = "wasm")]
 pub mod wasm_loader;
+#[cfg(feature = "wasm")]
+mod wasm_module;
+
+#[cfg(all(feature = "vm", feature = "wasm"))]
+pub mod loaders;
 
 #[allow(unused_mut)]
 pub fn create() -> (impl Resolver, impl Loader) {
@@ -0,0 +1,256 @@
+//! Management of the wasm loaders installed in `DirConfig::loaders_dir`
+
+use std::path::{Path, PathBuf};
+
+use wasmtime::Engine;
+
+use crate::{
+    utils::{sha256_hex, write_atomic},
+    wasm_loader::{file_stamp, open, read_manifest, Manifest, Sandbox, MANIFEST},
+    DirConfig,
+};
+
+/// A loader installed in the loaders directory
+#[derive(Clone, Debug)]
+pub struct InstalledLoader {
+    pub name: String,
+    pub path: PathBuf,
+    /// Extensions the loader transforms
+    pub extensions: Vec<String>,
+    /// Version of the loader interface
+    pub version: u32,
+    /// Hex encoded sha256 of the wasm file
+    pub checksum: String,
+}
+
+fn normalize_checksum(checksum: &str) -> String {
+    checksum
+        .trim()
+        .trim_start_matches("sha256:")
+        .to_ascii_lowercase()
+}
+
+fn check(path: &Path, actual: &str, expected: &str) -> anyhow::Result<()> {
+    if normalize_checksum(expected) != actual {
+        anyhow::bail!(
+            "checksum mismatch for {:?}: expected {}, got {}",
+            path,
+            normalize_checksum(expected),
+            actual
+        );
+    }
+    Ok(())
+}
+
+/// Instantiate the loader at `path` to find out what it handles
+async fn inspect(path: PathBuf) -> anyhow::Result<InstalledLoader> {
+    let bytes = tokio::fs::read(&path).await?;
+
+    tokio::task::spawn_blocking(move || {
+        let loader = open(Engine::default(), &path, None, &Sandbox::default())?;
+
+        let name = path
+            .file_stem()
+            .map(|m| m.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        Ok(InstalledLoader {
+            name,
+            extensions: loader.extensions().to_vec(),
+            version: loader.version(),
+            checksum: sha256_hex(&bytes),
+            path,
+        })
+    })
+    .await?
+}
+
+fn loader_path(cfg: &DirConfig, name: &str) -> anyhow::Result<PathBuf> {
+    if name.is_empty() || name.contains(|c| c == '/' || c == '\\') || name.starts_with('.') {
+        anyhow::bail!("invalid loader name: {:?}", name);
+    }
+
+    Ok(cfg.loaders_dir().join(name).with_extension("wasm"))
+}
+
+/// Install the loader at `source`, named after its file stem. A configuration
+/// file next to it (`typescript.toml` for `typescript.wasm`) is installed too.
+///
+/// The loader is instantiated before it is copied, so broken modules are
+/// rejected, and checked against `expected_checksum` when given
+pub async fn install(
+    cfg: &DirConfig,
+    source: impl AsRef<Path>,
+    expected_checksum: Option<&str>,
+) -> anyhow::Result<InstalledLoader> {
+    let source = source.as_ref();
+
+    let name = source
+        .file_stem()
+        .map(|m| m.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow::anyhow!("invalid loader path: {:?}", source))?;
+
+    let mut loader = inspect(source.to_path_buf()).await?;
+
+    if let Some(expected) = expected_checksum {
+        check(source, &loader.checksum, expected)?;
+    }
+
+    let dest = loader_path(cfg, &name)?;
+    tokio::fs::create_dir_all(cfg.loaders_dir()).await?;
+
+    write_atomic(&dest, tokio::fs::read(source).await?)?;
+
+    let sidecar = source.with_extension("toml");
+    if sidecar.exists() {
+        tokio::fs::copy(&sidecar, dest.with_extension("toml")).await?;
+    }
+
+    loader.path = dest;
+
+    Ok(loader)
+}
+
+/// What the manifest written by `open_path` records about the loader at
+/// `path`, as long as the file has not changed since
+async fn from_manifest(manifest: &Manifest, path: PathBuf) -> anyhow::Result<InstalledLoader> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (size, modified) = file_stamp(&tokio::fs::metadata(&path).await?);
+
+    match manifest.get(&file_name) {
+        Some(entry) if entry.size == size && entry.modified == modified && entry.version != 0 => {
+            let bytes = tokio::fs::read(&path).await?;
+            Ok(InstalledLoader {
+                name: path
+                    .file_stem()
+                    .map(|m| m.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                extensions: entry.extensions.clone(),
+                version: entry.version,
+                checksum: sha256_hex(&bytes),
+                path,
+            })
+        }
+        _ => inspect(path).await,
+    }
+}
+
+/// Every installed loader, sorted by name. Loaders are described from the
+/// manifest in the cache directory and only instantiated when it is missing
+/// or outdated. A loader that can not be inspected is reported as an error
+/// without hiding the others
+pub async fn list(cfg: &DirConfig) -> anyhow::Result<Vec<anyhow::Result<InstalledLoader>>> {
+    let dir = cfg.loaders_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let manifest = read_manifest(&cfg.cache_dir().join(MANIFEST)).await;
+
+    let mut stream = tokio::fs::read_dir(&dir).await?;
+    let mut paths = Vec::new();
+
+    while let Some(next) = stream.next_entry().await? {
+        let path = next.path();
+        if path
+            .extension()
+            .map(|ext| ext == "wasm")
+            .unwrap_or_default()
+        {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+
+    let mut loaders = Vec::with_capacity(paths.len());
+    for path in paths {
+        let loader = from_manifest(&manifest, path.clone())
+            .await
+            .map_err(|err| err.context(format!("broken loader {:?}", path)));
+        loaders.push(loader);
+    }
+
+    Ok(loaders)
+}
+
+/// Remove the loader `name` along with its configuration file
+pub async fn remove(cfg: &DirConfig, name: &str) -> anyhow::Result<()> {
+    let path = loader_path(cfg, name)?;
+    if !path.exists() {
+        anyhow::bail!("loader {} is not installed", name);
+    }
+
+    tokio::fs::remove_file(&path).await?;
+
+    let sidecar = path.with_extension("toml");
+    if sidecar.exists() {
+        tokio::fs::remove_file(sidecar).await?;
+    }
+
+    Ok(())
+}
+
+/// Check the installed loader `name` against `expected` (hex encoded sha256,
+/// optionally prefixed with `sha256:`)
+pub async fn verify(cfg: &DirConfig, name: &str, expected: &str) -> anyhow::Result<()> {
+    let path = loader_path(cfg, name)?;
+    if !path.exists() {
+        anyhow::bail!("loader {} is not installed", name);
+    }
+
+    let bytes = tokio::fs::read(&path).await?;
+    check(&path, &sha256_hex(&bytes), expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm_loader::ManifestEntry;
+
+    #[tokio::test]
+    async fn lists_from_manifest_and_reports_broken_loaders() {
+        let root = tempfile::tempdir().unwrap();
+        let cfg = DirConfig::new(Some(root.path().to_path_buf()))
+            .await
+            .unwrap();
+
+        // Neither file is a valid module, so only the manifest can describe them
+        std::fs::create_dir_all(cfg.loaders_dir()).unwrap();
+        let known = cfg.loaders_dir().join("known.wasm");
+        let broken = cfg.loaders_dir().join("broken.wasm");
+        std::fs::write(&known, b"not wasm").unwrap();
+        std::fs::write(&broken, b"not wasm either").unwrap();
+
+        let (size, modified) = file_stamp(&std::fs::metadata(&known).unwrap());
+        let mut manifest = Manifest::default();
+        manifest.insert(
+            "known.wasm".to_string(),
+            ManifestEntry {
+                size,
+                modified,
+                extensions: vec!["ts".to_string()],
+                resolver: false,
+                version: 2,
+            },
+        );
+        std::fs::write(
+            cfg.cache_dir().join(MANIFEST),
+            serde_json::to_vec(&manifest).unwrap(),
+        )
+        .unwrap();
+
+        let listed = list(&cfg).await.unwrap();
+        assert_eq!(listed.len(), 2);
+        assert!(listed[0].is_err());
+
+        let loader = listed[1].as_ref().unwrap();
+        assert_eq!(loader.name, "known");
+        assert_eq!(loader.extensions, ["ts"]);
+        assert_eq!(loader.version, 2);
+        assert_eq!(loader.checksum, sha256_hex(b"not wasm"));
+    }
+}
@@ -1,11 +1,12 @@
 #[allow(unused_macros)]
+/// Exception with the message of `$error`, located at the host code raising it
 macro_rules! throw {
     ($error: expr) => {
         rquickjs::Error::Exception {
             message: $error.to_string(),
-            file: String::default(),
-            line: 0,
-            stack: String::default(),
+            file: file!().to_string(),
+            line: line!() as _,
+            stack: format!("    at <native> ({}:{})", file!(), line!()),
         }
     };
     () => {
@@ -105,7 +106,7 @@ macro_rules! stream {
 #[cfg(any(feature = "fs", feature = "os"))]
 macro_rules! readwriter {
     ($file: ident) => {
-        stream!(tokio_stream::wrappers::LinesStream<tokio::io::BufReader<$crate::FileDesc<$file>>>);
+        stream!($crate::Lines<$file>);
         impl ClassDef for $crate::FileDesc<$file> {
             /// The name of a class
             const CLASS_NAME: &'static str = <$file as $crate::Named>::NAME;
@@ -0,0 +1,278 @@
+//! Resolution of bare specifiers to packages installed in `node_modules`.
+//!
+//! Follows the node algorithm for ESM packages: `node_modules` directories are
+//! searched from the importing module upwards, and the `exports` field of
+//! `package.json` (including subpath patterns and conditions) takes precedence
+//! over `module` and `main`.
+
+use std::path::{Path, PathBuf};
+
+use rquickjs::{Ctx, Error, Resolver, Result};
+use serde_json::Value;
+
+use crate::utils::normalize_path;
+
+/// Conditions matched in `exports`, in addition to `default`
+pub const CONDITIONS: &[&str] = &["scriptor", "import", "module"];
+
+/// Extensions tried for files referenced without one
+const EXTENSIONS: &[&str] = &["js", "mjs", "json"];
+
+#[derive(Clone, Debug)]
+pub struct NodeResolver {
+    cwd: PathBuf,
+    conditions: Vec<String>,
+}
+
+impl NodeResolver {
+    pub fn new(cwd: impl Into<PathBuf>) -> NodeResolver {
+        NodeResolver {
+            cwd: cwd.into(),
+            conditions: CONDITIONS.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    /// Add a condition matched in `exports`. As in node, when a package lists
+    /// several matching conditions the first one it lists wins, whatever the
+    /// order they were added in
+    pub fn add_condition(&mut self, condition: impl Into<String>) -> &mut Self {
+        self.conditions.push(condition.into());
+        self
+    }
+
+    fn matches(&self, condition: &str) -> bool {
+        condition == "default" || self.conditions.iter().any(|m| m == condition)
+    }
+
+    /// Find `name` in the `node_modules` directories above `base`
+    pub fn resolve_path(&self, base: &str, name: &str) -> Option<PathBuf> {
+        if is_path(name) {
+            return None;
+        }
+
+        let (package, subpath) = split_package(name)?;
+
+        let base_dir = self
+            .cwd
+            .join(Path::new(base).parent().unwrap_or_else(|| Path::new("")));
+
+        base_dir
+            .ancestors()
+            .map(|dir| dir.join("node_modules").join(package))
+            .find(|dir| dir.is_dir())
+            .and_then(|dir| self.resolve_package(&dir, &subpath))
+            .map(|path| normalize_path(&path))
+    }
+
+    fn resolve_package(&self, dir: &Path, subpath: &str) -> Option<PathBuf> {
+        let package = std::fs::read_to_string(dir.join("package.json"))
+            .ok()
+            .and_then(|json| serde_json::from_str::<Value>(&json).ok())
+            .unwrap_or(Value::Null);
+
+        // A package with `exports` only exposes what it lists
+        if let Some(exports) = package.get("exports") {
+            let target = self.resolve_exports(exports, subpath)?;
+            return existing(dir.join(target));
+        }
+
+        if subpath != "." {
+            return resolve_file(&dir.join(subpath));
+        }
+
+        ["module", "main"]
+            .iter()
+            .filter_map(|field| package.get(field)?.as_str())
+            .find_map(|entry| resolve_file(&dir.join(entry)))
+            .or_else(|| resolve_file(&dir.join("index")))
+    }
+
+    fn resolve_exports(&self, exports: &Value, subpath: &str) -> Option<String> {
+        let is_subpath_map = exports
+            .as_object()
+            .map(|map| map.keys().all(|key| key.starts_with('.')))
+            .unwrap_or_default();
+
+        if !is_subpath_map {
+            // Sugar for `{ ".": exports }`
+            return match subpath {
+                "." => self.resolve_target(exports, None),
+                _ => None,
+            };
+        }
+
+        let map = exports.as_object()?;
+
+        if let Some(target) = map.get(subpath) {
+            return self.resolve_target(target, None);
+        }
+
+        // Subpath patterns: the longest prefix before the `*` wins
+        map.iter()
+            .filter_map(|(key, target)| {
+                let (prefix, suffix) = key.split_once('*')?;
+                let star = subpath.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                Some((prefix.len(), star, target))
+            })
+            .max_by_key(|(len, _, _)| *len)
+            .and_then(|(_, star, target)| self.resolve_target(target, Some(star)))
+    }
+
+    fn resolve_target(&self, target: &Value, star: Option<&str>) -> Option<String> {
+        match target {
+            Value::String(target) => {
+                if !target.starts_with("./") {
+                    return None;
+                }
+                Some(match star {
+                    Some(star) => target.replace('*', star),
+                    None => target.clone(),
+                })
+            }
+            Value::Array(targets) => targets
+                .iter()
+                .find_map(|target| self.resolve_target(target, star)),
+            // Conditions are tried in the order the package lists them
+            Value::Object(conditions) => conditions
+                .iter()
+                .filter(|(condition, _)| self.matches(condition))
+                .find_map(|(_, target)| self.resolve_target(target, star)),
+            _ => None,
+        }
+    }
+}
+
+impl Resolver for NodeResolver {
+    fn resolve<'js>(&mut self, _ctx: Ctx<'js>, base: &str, name: &str) -> Result<String> {
+        match self.resolve_path(base, name) {
+            Some(path) => Ok(path.to_string_lossy().to_string()),
+            None => Err(Error::new_resolving(base, name)),
+        }
+    }
+}
+
+fn is_path(name: &str) -> bool {
+    name.starts_with("./") || name.starts_with("../") || name.starts_with('/')
+}
+
+/// Split `@scope/name/sub/path` into the package and `./sub/path`
+fn split_package(name: &str) -> Option<(&str, String)> {
+    let mut parts = name.splitn(if name.starts_with('@') { 3 } else { 2 }, '/');
+
+    let package_len = if name.starts_with('@') {
+        let scope = parts.next()?;
+        let package = parts.next()?;
+        scope.len() + 1 + package.len()
+    } else {
+        parts.next()?.len()
+    };
+
+    let subpath = match parts.next() {
+        Some(rest) if !rest.is_empty() => format!("./{}", rest),
+        _ => ".".to_string(),
+    };
+
+    Some((&name[..package_len], subpath))
+}
+
+fn existing(path: PathBuf) -> Option<PathBuf> {
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// `path` as is, with one of `EXTENSIONS` added, or its `index` file
+fn resolve_file(path: &Path) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+
+    let with_extension = |path: &Path| {
+        EXTENSIONS.iter().find_map(|ext| {
+            let mut file = path.as_os_str().to_owned();
+            file.push(".");
+            file.push(ext);
+            existing(PathBuf::from(file))
+        })
+    };
+
+    with_extension(path).or_else(|| with_extension(&path.join("index")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn splits_packages() {
+        assert_eq!(split_package("pkg"), Some(("pkg", ".".to_string())));
+        assert_eq!(
+            split_package("pkg/sub/path"),
+            Some(("pkg", "./sub/path".to_string()))
+        );
+        assert_eq!(
+            split_package("@scope/pkg/sub"),
+            Some(("@scope/pkg", "./sub".to_string()))
+        );
+    }
+
+    #[test]
+    fn picks_conditions_in_package_order() {
+        let mut resolver = NodeResolver::new("/");
+        resolver.add_condition("browser");
+
+        let exports = json!({ "browser": "./browser.js", "import": "./import.js" });
+        assert_eq!(
+            resolver.resolve_exports(&exports, "."),
+            Some("./browser.js".to_string())
+        );
+
+        let exports = json!({ "require": "./require.js", "default": "./default.js" });
+        assert_eq!(
+            resolver.resolve_exports(&exports, "."),
+            Some("./default.js".to_string())
+        );
+    }
+
+    #[test]
+    fn matches_subpath_patterns() {
+        let resolver = NodeResolver::new("/");
+        let exports = json!({
+            ".": "./index.js",
+            "./*": "./lib/*.js",
+            "./features/*": "./features/*/index.js",
+        });
+
+        assert_eq!(
+            resolver.resolve_exports(&exports, "./features/x"),
+            Some("./features/x/index.js".to_string())
+        );
+        assert_eq!(
+            resolver.resolve_exports(&exports, "./util"),
+            Some("./lib/util.js".to_string())
+        );
+    }
+
+    #[test]
+    fn finds_packages_above_the_importer() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cwd = tmp.path();
+
+        let package = cwd.join("node_modules").join("pkg");
+        std::fs::create_dir_all(package.join("lib")).unwrap();
+        std::fs::create_dir_all(cwd.join("src").join("nested")).unwrap();
+        std::fs::write(package.join("package.json"), r#"{ "main": "./lib/main" }"#).unwrap();
+        std::fs::write(package.join("lib").join("main.js"), "").unwrap();
+
+        let resolver = NodeResolver::new(cwd);
+        assert_eq!(
+            resolver.resolve_path("src/nested/mod.js", "pkg"),
+            Some(package.join("lib").join("main.js"))
+        );
+        assert_eq!(resolver.resolve_path("src/nested/mod.js", "./pkg"), None);
+        assert_eq!(resolver.resolve_path("src/nested/mod.js", "missing"), None);
+    }
+}
@@ -1,9 +1,142 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
-use rquickjs::IntoJs;
+use rquickjs::{Ctx, FromJs, Func, IntoJs, Object, Opt, Result};
+
+use crate::utils::normalize_path;
 
 #[derive(IntoJs)]
 pub struct Process {
     argv: Vec<String>,
     env: HashMap<String, String>,
+    pid: u32,
+    platform: String,
+}
+
+impl Process {
+    pub fn new(argv: Vec<String>) -> Process {
+        Process {
+            argv,
+            env: std::env::vars().collect(),
+            pid: std::process::id(),
+            platform: std::env::consts::OS.to_string(),
+        }
+    }
+}
+
+impl Default for Process {
+    fn default() -> Self {
+        Process::new(std::env::args().collect())
+    }
+}
+
+/// Exit code requested by a script through `process.exit`
+#[derive(Clone, Default)]
+pub struct Exit(Arc<Mutex<Option<i32>>>);
+
+impl Exit {
+    pub fn set(&self, code: i32) {
+        *self.0.lock().unwrap() = Some(code);
+    }
+
+    pub fn get(&self) -> Option<i32> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Set up the `process` global. `cwd` is the working directory of the vm,
+/// which `process.chdir` changes without touching the host process
+pub fn init(ctx: Ctx<'_>, process: Process, exit: Exit, cwd: PathBuf) -> Result<()> {
+    let value = process.into_js(ctx)?;
+    let object = Object::from_js(ctx, value)?;
+
+    let cwd = Arc::new(Mutex::new(cwd));
+
+    object.set(
+        "cwd",
+        Func::new("cwd", {
+            let cwd = cwd.clone();
+            move || cwd.lock().unwrap().to_string_lossy().to_string()
+        }),
+    )?;
+
+    object.set(
+        "chdir",
+        Func::new("chdir", move |path: String| -> Result<()> {
+            let mut cwd = cwd.lock().unwrap();
+            let dir = normalize_path(&cwd.join(&path));
+
+            if !std::fs::metadata(&dir)?.is_dir() {
+                return Err(throw!(format!(
+                    "ENOTDIR: not a directory, chdir '{}'",
+                    path
+                )));
+            }
+
+            *cwd = dir;
+            Ok(())
+        }),
+    )?;
+
+    object.set(
+        "exit",
+        Func::new("exit", move |code: Opt<i32>| -> Result<()> {
+            exit.set(code.0.unwrap_or(0));
+            // Unwind the current stack, the runtime interrupt handler takes care of
+            // anything that tries to catch this
+            Err(throw!("process.exit() called"))
+        }),
+    )?;
+
+    ctx.globals().set("process", object)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rquickjs::{Context, Runtime};
+
+    fn with_process<R>(cwd: PathBuf, f: impl FnOnce(Ctx<'_>) -> R) -> R {
+        let rt = Runtime::new().unwrap();
+        let ctx = Context::full(&rt).unwrap();
+        ctx.with(|ctx| {
+            init(ctx, Process::new(Vec::new()), Exit::default(), cwd).unwrap();
+            f(ctx)
+        })
+    }
+
+    #[test]
+    fn cwd_is_the_vm_directory() {
+        let dir = std::env::temp_dir();
+        let cwd: String = with_process(dir.clone(), |ctx| ctx.eval("process.cwd()").unwrap());
+        assert_eq!(cwd, dir.to_string_lossy());
+    }
+
+    #[test]
+    fn chdir_leaves_the_host_process_alone() {
+        let host = std::env::current_dir().unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+
+        let cwd: String = with_process(dir.clone(), |ctx| {
+            ctx.eval("process.chdir('sub/../sub'); process.cwd()")
+                .unwrap()
+        });
+
+        assert_eq!(cwd, dir.join("sub").to_string_lossy());
+        assert_eq!(std::env::current_dir().unwrap(), host);
+    }
+
+    #[test]
+    fn chdir_rejects_missing_directories() {
+        let dir = std::env::temp_dir();
+        let ret: Result<()> = with_process(dir, |ctx| ctx.eval("process.chdir('does-not-exist')"));
+        assert!(ret.is_err());
+    }
 }
@@ -0,0 +1,354 @@
+//! Importing modules from `http://` and `https://` URLs.
+//!
+//! Fetched modules are stored in the cache directory, and the sha256 of every
+//! module is recorded in a lockfile next to the project. Modules whose content
+//! no longer matches the lockfile are refused. In offline mode nothing is
+//! fetched, and modules missing from the cache fail to load.
+//!
+//! Relative imports in a remote module are resolved against its URL.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc,
+};
+
+use reqwest::Url;
+use rquickjs::{Ctx, Error, Loaded, Loader, Module, Resolver, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::rewrite_import_attributes,
+    utils::{sha256_hex, write_atomic},
+    SourceMaps,
+};
+
+/// Name of the lockfile picked up from the working directory
+pub const LOCKFILE: &str = "scriptor.lock";
+
+/// Integrity hashes of remote modules, by URL
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Lockfile {
+    #[serde(default)]
+    remote: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    /// Read the lockfile at `path`, or an empty one if it does not exist
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Lockfile> {
+        let path = path.as_ref();
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Lockfile::default()),
+            Err(err) => return Err(err),
+        };
+
+        serde_json::from_str(&json).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.to_string_lossy(), err),
+            )
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        write_atomic(path.as_ref(), format!("{}\n", json).as_bytes())
+    }
+
+    pub fn get(&self, url: &str) -> Option<&str> {
+        self.remote.get(url).map(String::as_str)
+    }
+}
+
+struct Lock {
+    path: PathBuf,
+    file: Lockfile,
+}
+
+/// Resolves and loads remote modules
+#[derive(Clone)]
+pub struct RemoteModules {
+    cache: PathBuf,
+    offline: bool,
+    lock: Rc<RefCell<Lock>>,
+    downloader: Rc<RefCell<Option<Downloader>>>,
+    source_maps: SourceMaps,
+}
+
+impl RemoteModules {
+    /// Cache modules in `cache` and record their hashes in the lockfile at `lockfile`
+    pub fn new(
+        cache: impl Into<PathBuf>,
+        lockfile: impl Into<PathBuf>,
+    ) -> io::Result<RemoteModules> {
+        let path = lockfile.into();
+        let file = Lockfile::open(&path)?;
+
+        Ok(RemoteModules {
+            cache: cache.into(),
+            offline: false,
+            lock: Rc::new(RefCell::new(Lock { path, file })),
+            downloader: Rc::default(),
+            source_maps: SourceMaps::default(),
+        })
+    }
+
+    /// Only load modules already in the cache
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// Register the source maps of transpiled modules in `source_maps`
+    pub fn set_source_maps(&mut self, source_maps: SourceMaps) {
+        self.source_maps = source_maps;
+    }
+
+    /// Fetch `url` with the downloader, started by the first download
+    fn download(&self, url: &str) -> io::Result<Vec<u8>> {
+        let mut downloader = self.downloader.borrow_mut();
+        let downloader = match &mut *downloader {
+            Some(downloader) => downloader,
+            None => downloader.insert(Downloader::start()?),
+        };
+        downloader.download(url)
+    }
+
+    fn cache_path(&self, url: &Url) -> PathBuf {
+        let host = url.host_str().unwrap_or("unknown");
+        let host = match url.port() {
+            Some(port) => format!("{}_{}", host, port),
+            None => host.to_string(),
+        };
+
+        self.cache
+            .join(url.scheme())
+            .join(host)
+            .join(integrity(url.as_str().as_bytes()))
+    }
+
+    /// Source of the module at `url`, from the cache or else fetched
+    pub fn fetch(&self, url: &str) -> io::Result<Vec<u8>> {
+        let parsed =
+            Url::parse(url).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let path = self.cache_path(&parsed);
+
+        let (bytes, cached) = match std::fs::read(&path) {
+            Ok(bytes) => (bytes, true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if self.offline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{} is not cached and offline mode is enabled", url),
+                    ));
+                }
+                (self.download(url)?, false)
+            }
+            Err(err) => return Err(err),
+        };
+
+        let hash = integrity(&bytes);
+
+        let mut lock = self.lock.borrow_mut();
+        match lock.file.get(url) {
+            Some(expected) if expected != hash => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "integrity check failed for {}: lockfile has {}, got {}",
+                        url, expected, hash
+                    ),
+                ));
+            }
+            Some(_) => {}
+            None => {
+                lock.file.remote.insert(url.to_string(), hash);
+                lock.file.save(&lock.path)?;
+            }
+        }
+
+        if !cached {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            write_atomic(&path, &bytes)?;
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl Resolver for RemoteModules {
+    fn resolve<'js>(&mut self, _ctx: Ctx<'js>, base: &str, name: &str) -> Result<String> {
+        let url = if is_remote(name) {
+            Url::parse(name)
+        } else if is_remote(base) && is_path(name) {
+            Url::parse(base).and_then(|base| base.join(name))
+        } else {
+            return Err(Error::new_resolving(base, name));
+        };
+
+        match url {
+            Ok(url) => Ok(url.to_string()),
+            Err(err) => Err(Error::new_resolving_message(base, name, err.to_string())),
+        }
+    }
+}
+
+impl Loader for RemoteModules {
+    fn load<'js>(&mut self, ctx: Ctx<'js>, name: &str) -> Result<Module<'js, Loaded<()>>> {
+        if !is_remote(name) {
+            return Err(Error::new_loading(name));
+        }
+
+        let source = self.source(name)?;
+        Ok(Module::new(ctx, name, source)?.into_loaded())
+    }
+}
+
+impl RemoteModules {
+    /// Javascript source of the module at `url`
+    pub(crate) fn source(&self, url: &str) -> Result<String> {
+        let bytes = self
+            .fetch(url)
+            .map_err(|err| Error::new_loading_message(url, err.to_string()))?;
+        let source = String::from_utf8(bytes)
+            .map_err(|err| Error::new_loading_message(url, err.to_string()))?;
+        let source = rewrite_import_attributes(&source).into_owned();
+
+        #[cfg(feature = "typescript")]
+        let source = match Url::parse(url) {
+            Ok(parsed) if crate::typescript_loader::is_typescript(parsed.path()) => {
+                let (code, map) = crate::typescript_loader::transpile(url, source)?;
+                if let Some(map) = &map {
+                    self.source_maps.insert(url, map);
+                }
+                code
+            }
+            _ => source,
+        };
+
+        Ok(source)
+    }
+}
+
+pub(crate) fn is_remote(name: &str) -> bool {
+    name.starts_with("http://") || name.starts_with("https://")
+}
+
+fn is_path(name: &str) -> bool {
+    name.starts_with("./") || name.starts_with("../") || name.starts_with('/')
+}
+
+/// `sha256-` followed by the hex encoded sha256 of `bytes`
+fn integrity(bytes: &[u8]) -> String {
+    format!("sha256-{}", sha256_hex(bytes))
+}
+
+type Request = (String, mpsc::Sender<io::Result<Vec<u8>>>);
+
+/// Fetches modules on a thread of its own. Loading is synchronous and may
+/// happen on the thread running the vm's executor, so requests are handed to
+/// a thread with a runtime and client of its own, which stops with the last
+/// clone of the `RemoteModules`
+struct Downloader {
+    requests: mpsc::Sender<Request>,
+}
+
+impl Downloader {
+    fn start() -> io::Result<Downloader> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let (requests, rx) = mpsc::channel::<Request>();
+
+        std::thread::spawn(move || {
+            let client = reqwest::Client::new();
+            for (url, reply) in rx {
+                let _ = reply.send(rt.block_on(get(&client, &url)));
+            }
+        });
+
+        Ok(Downloader { requests })
+    }
+
+    fn download(&self, url: &str) -> io::Result<Vec<u8>> {
+        let stopped = || io::Error::new(io::ErrorKind::Other, "download thread stopped");
+
+        let (reply, rx) = mpsc::channel();
+        self.requests
+            .send((url.to_string(), reply))
+            .map_err(|_| stopped())?;
+        rx.recv().map_err(|_| stopped())?
+    }
+}
+
+async fn get(client: &reqwest::Client, url: &str) -> io::Result<Vec<u8>> {
+    let other = |err: reqwest::Error| io::Error::new(io::ErrorKind::Other, err);
+
+    let resp = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(other)?;
+    Ok(resp.bytes().await.map_err(other)?.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn modules() -> (RemoteModules, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut modules =
+            RemoteModules::new(dir.path().join("cache"), dir.path().join(LOCKFILE)).unwrap();
+        modules.set_offline(true);
+        (modules, dir)
+    }
+
+    /// Put `source` in the cache of `modules` as if fetched from `url`
+    fn cache(modules: &RemoteModules, url: &str, source: &str) {
+        let path = modules.cache_path(&Url::parse(url).unwrap());
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, source).unwrap();
+    }
+
+    #[test]
+    fn records_and_checks_integrity() {
+        let (modules, dir) = modules();
+        let url = "https://example.com/mod.js";
+
+        cache(&modules, url, "export default 1;");
+        assert_eq!(modules.fetch(url).unwrap(), b"export default 1;");
+
+        let lockfile = Lockfile::open(dir.path().join(LOCKFILE)).unwrap();
+        assert_eq!(
+            lockfile.get(url),
+            Some(integrity(b"export default 1;").as_str())
+        );
+
+        cache(&modules, url, "export default 2;");
+        let err = modules.fetch(url).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn offline_fails_for_uncached_modules() {
+        let (modules, _dir) = modules();
+        let err = modules.fetch("https://example.com/missing.js").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn missing_lockfile_is_empty() {
+        let lockfile = Lockfile::open("/scriptor-missing/scriptor.lock").unwrap();
+        assert_eq!(lockfile.get("https://example.com/mod.js"), None);
+    }
+}
@@ -0,0 +1,488 @@
+use rquickjs::{Function, Object, Persistent, Promise, Result, Value};
+
+use crate::{
+    lexer::{Token, TokenKind, Tokens},
+    vm::Vm,
+};
+
+static REPL: &str = include_str!("../lib/repl.js");
+
+pub struct Repl<'a> {
+    vm: &'a mut Vm,
+    transform: Persistent<Function<'static>>,
+    inspect: Persistent<Function<'static>>,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(vm: &'a mut Vm) -> Result<Repl<'a>> {
+        let (transform, inspect) = vm.with(|ctx| {
+            let module = ctx.compile("repl", REPL)?;
+            Result::<_>::Ok((
+                Persistent::save(ctx, module.get::<_, Function>("transform")?),
+                Persistent::save(ctx, module.get::<_, Function>("inspect")?),
+            ))
+        })?;
+
+        Ok(Repl {
+            vm,
+            transform,
+            inspect,
+        })
+    }
+
+    /// The exit code passed to `process.exit` during the session, if any
+    pub fn exit_code(&self) -> Option<i32> {
+        self.vm.exit_code()
+    }
+
+    /// Evaluate a chunk of input and return the formatted result, if any
+    pub async fn eval(&mut self, source: &str) -> Result<Option<String>> {
+        let ret = self
+            .vm
+            .with(|ctx| {
+                let transform = self.transform.clone().restore(ctx)?;
+                let inspect = self.inspect.clone().restore(ctx)?;
+
+                let code = rewrite_imports(source);
+
+                // Only input awaiting something is wrapped in an async function,
+                // where declarations have to be copied to the global scope
+                let (code, wrapped) = if Tokens::new(&code).any(|t| t.is_word("await")) {
+                    let body = export_declarations(&code);
+                    let transformed: Object = transform.call((code, body))?;
+                    (transformed.get("code")?, transformed.get("wrapped")?)
+                } else {
+                    (code, false)
+                };
+
+                let value: Value = ctx.eval(code)?;
+
+                inspect.call::<_, Promise<Option<String>>>((value, wrapped))
+            })?
+            .await?;
+
+        self.vm.finish(self.vm.idle()).await?;
+
+        Ok(ret)
+    }
+}
+
+/// Returns true if the input has unbalanced brackets, an unterminated string
+/// or template, or an unterminated comment, and more lines should be read
+/// before evaluating it
+pub fn is_incomplete(source: &str) -> bool {
+    let mut tokens = Tokens::new(source);
+    let mut depth = 0i32;
+    let mut last = None;
+
+    for token in tokens.by_ref() {
+        if token.kind == TokenKind::Punct {
+            match token.text {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => depth -= 1,
+                _ => {}
+            }
+        }
+        last = Some(token);
+    }
+
+    let open_literal = last
+        .filter(|token| matches!(token.kind, TokenKind::String | TokenKind::Template))
+        .filter(|token| !token.terminated)
+        .is_some();
+
+    depth > 0 || open_literal || tokens.in_comment()
+}
+
+fn is_punct(tokens: &[Token], i: usize, punct: char) -> bool {
+    matches!(tokens.get(i), Some(token) if token.is_punct(punct))
+}
+
+/// Whether `tokens[i]` starts a statement, so `import` there is a declaration
+/// rather than `import()` or `import.meta`
+fn is_statement_start(source: &str, tokens: &[Token], i: usize) -> bool {
+    let follows = i == 0 || {
+        let prev = tokens[i - 1];
+        matches!(prev.text, ";" | "{" | "}") || source[prev.end()..tokens[i].start].contains('\n')
+    };
+    let called = tokens
+        .get(i + 1)
+        .filter(|next| next.is_punct('(') || next.is_punct('.'))
+        .is_some();
+    follows && !called
+}
+
+/// Declaration replacing the static import at `tokens[i]`, and the index of
+/// the token after the import. `None` when the import is not understood, so
+/// the engine gets to report it
+fn parse_import(tokens: &[Token], mut i: usize) -> Option<(String, usize)> {
+    let word = |i: usize| tokens.get(i).filter(|t| t.kind == TokenKind::Word);
+
+    i += 1;
+    let mut default = None;
+    let mut namespace = None;
+    let mut named = Vec::new();
+
+    if let Some(token) = word(i).filter(|t| !t.is_word("from")) {
+        default = Some(token.text);
+        i += 1;
+        if tokens.get(i)?.is_punct(',') {
+            i += 1;
+        }
+    }
+
+    let token = tokens.get(i)?;
+    if token.is_punct('*') {
+        word(i + 1).filter(|t| t.is_word("as"))?;
+        namespace = Some(word(i + 2)?.text);
+        i += 3;
+    } else if token.is_punct('{') {
+        i += 1;
+        while !tokens.get(i)?.is_punct('}') {
+            let name = tokens.get(i)?;
+            if !matches!(name.kind, TokenKind::Word | TokenKind::String) {
+                return None;
+            }
+
+            if matches!(word(i + 1), Some(t) if t.is_word("as")) {
+                named.push(format!("{}: {}", name.text, word(i + 2)?.text));
+                i += 3;
+            } else {
+                named.push(name.text.to_string());
+                i += 1;
+            }
+
+            if tokens.get(i)?.is_punct(',') {
+                i += 1;
+            }
+        }
+        i += 1;
+    }
+
+    let has_bindings = default.is_some() || namespace.is_some() || !named.is_empty();
+    if has_bindings {
+        word(i).filter(|t| t.is_word("from"))?;
+        i += 1;
+    }
+
+    let specifier = tokens.get(i).filter(|t| t.kind == TokenKind::String)?.text;
+    i += 1;
+
+    if is_punct(tokens, i, ';') {
+        i += 1;
+    }
+
+    let module = format!("await import({})", specifier);
+    let code = match (default, namespace) {
+        (_, Some(namespace)) => {
+            let default = default
+                .map(|default| format!(", {} = {}.default", default, namespace))
+                .unwrap_or_default();
+            format!("const {} = {}{};", namespace, module, default)
+        }
+        _ if has_bindings => {
+            named.extend(default.map(|default| format!("default: {}", default)));
+            format!("const {{ {} }} = {};", named.join(", "), module)
+        }
+        _ => format!("{};", module),
+    };
+
+    Some((code, i))
+}
+
+/// Replace static imports with `const` declarations of what they import,
+/// awaiting a dynamic import. Lines are kept where they were
+fn rewrite_imports(source: &str) -> String {
+    let tokens = Tokens::new(source).collect::<Vec<_>>();
+
+    let mut out = String::with_capacity(source.len());
+    let mut last = 0;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = tokens[i];
+        if !token.is_word("import") || !is_statement_start(source, &tokens, i) {
+            i += 1;
+            continue;
+        }
+
+        let (code, next) = match parse_import(&tokens, i) {
+            Some(import) => import,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+
+        let end = tokens[next - 1].end();
+        out.push_str(&source[last..token.start]);
+        out.push_str(&code);
+        out.extend(source[token.start..end].matches('\n'));
+
+        last = end;
+        i = next;
+    }
+
+    out.push_str(&source[last..]);
+    out
+}
+
+/// Skip the initializer starting after the `=` at `tokens[i]`, up to the `,`
+/// or `;` ending it, a closing bracket of the enclosing pattern, or a line
+/// break after a complete value
+fn skip_initializer(source: &str, tokens: &[Token], mut i: usize) -> usize {
+    let mut depth = 0;
+    i += 1;
+
+    while let Some(token) = tokens.get(i) {
+        if token.kind == TokenKind::Punct {
+            match token.text {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" if depth == 0 => break,
+                ")" | "]" | "}" => depth -= 1,
+                "," | ";" if depth == 0 => break,
+                _ => {}
+            }
+        } else if depth == 0 {
+            // A value ending a line, followed by another one, ends the statement
+            let prev = tokens[i - 1];
+            let ends_value = prev.kind != TokenKind::Punct || matches!(prev.text, ")" | "]" | "}");
+            if ends_value && source[prev.end()..token.start].contains('\n') {
+                break;
+            }
+        }
+        i += 1;
+    }
+
+    i
+}
+
+/// Add the names bound by the binding target at `tokens[i]`, an identifier
+/// or a destructuring pattern, and return the index after it
+fn binding_names<'a>(
+    source: &str,
+    tokens: &[Token<'a>],
+    mut i: usize,
+    names: &mut Vec<&'a str>,
+) -> usize {
+    let token = match tokens.get(i) {
+        Some(token) => *token,
+        None => return i,
+    };
+
+    let close = match token.text {
+        "{" if token.kind == TokenKind::Punct => '}',
+        "[" if token.kind == TokenKind::Punct => ']',
+        _ => {
+            if token.kind == TokenKind::Word {
+                names.push(token.text);
+            }
+            return i + 1;
+        }
+    };
+
+    i += 1;
+    while let Some(token) = tokens.get(i) {
+        if token.is_punct(close) {
+            return i + 1;
+        }
+
+        if token.is_punct(',') {
+            i += 1;
+            continue;
+        }
+
+        while is_punct(tokens, i, '.') {
+            i += 1;
+        }
+
+        if close == '}' {
+            let key = tokens.get(i).copied();
+            if matches!(key, Some(key) if key.is_punct('[')) {
+                // Computed key, always followed by `:` and a target
+                i = skip_initializer(source, tokens, i) + 2;
+            } else if is_punct(tokens, i + 1, ':') {
+                i += 2;
+            }
+        }
+
+        let next = binding_names(source, tokens, i, names);
+        i = next.max(i + 1);
+
+        if is_punct(tokens, i, '=') {
+            i = skip_initializer(source, tokens, i);
+        }
+    }
+
+    i
+}
+
+/// Names declared at the top level of `source` by `const`, `let`, `var`,
+/// `function` and `class`
+fn declared_names(source: &str) -> Vec<&str> {
+    let tokens = Tokens::new(source).collect::<Vec<_>>();
+
+    let mut names = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+
+    while let Some(token) = tokens.get(i) {
+        match token.kind {
+            TokenKind::Punct => match token.text {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => depth -= 1,
+                _ => {}
+            },
+            TokenKind::Word if depth == 0 => match token.text {
+                "const" | "let" | "var" => {
+                    i += 1;
+                    loop {
+                        i = binding_names(source, &tokens, i, &mut names);
+                        if is_punct(&tokens, i, '=') {
+                            i = skip_initializer(source, &tokens, i);
+                        }
+                        if !is_punct(&tokens, i, ',') {
+                            break;
+                        }
+                        i += 1;
+                    }
+                    continue;
+                }
+                "function" | "class" => {
+                    let mut next = i + 1;
+                    if is_punct(&tokens, next, '*') {
+                        next += 1;
+                    }
+                    if let Some(name) = tokens.get(next).filter(|t| t.kind == TokenKind::Word) {
+                        if !name.is_word("extends") {
+                            names.push(name.text);
+                        }
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        i += 1;
+    }
+
+    names
+}
+
+/// `source` followed by copying the names it declares at the top level to the
+/// global object, so they outlive the async function it is run in
+fn export_declarations(source: &str) -> String {
+    let names = declared_names(source);
+    if names.is_empty() {
+        return source.to_string();
+    }
+
+    format!(
+        "{}\n;Object.assign(globalThis, {{ {} }});",
+        source,
+        names.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn balanced_input_is_complete() {
+        assert!(!is_incomplete("const a = [1, 2];"));
+        assert!(!is_incomplete("f({ a: \"}\" })"));
+    }
+
+    #[test]
+    fn open_brackets_and_strings_are_incomplete() {
+        assert!(is_incomplete("function f() {"));
+        assert!(is_incomplete("const a = 'abc"));
+        assert!(is_incomplete("/* comment"));
+    }
+
+    #[test]
+    fn quotes_in_regex_literals_are_not_strings() {
+        assert!(!is_incomplete("const re = /'/;"));
+        assert!(!is_incomplete("'a\"b'.replace(/\"/g, '')"));
+        assert!(!is_incomplete("return /[/']/.test(s)"));
+        assert!(is_incomplete("if (/'/.test(s)) {"));
+    }
+
+    #[test]
+    fn division_is_not_a_regex() {
+        assert!(!is_incomplete("const a = b / 2, c = d / 3;"));
+        assert!(!is_incomplete("(a + b) / 'x'.length"));
+    }
+
+    #[test]
+    fn unterminated_templates_and_comments_are_incomplete() {
+        assert!(is_incomplete("const a = `abc\n${b}"));
+        assert!(!is_incomplete("const a = `abc\n${b}`"));
+        assert!(!is_incomplete("const a = '/*'"));
+    }
+
+    #[test]
+    fn rewrites_import_statements() {
+        assert_eq!(
+            rewrite_imports("import a, { b, c as d } from 'm';"),
+            "const { b, c: d, default: a } = await import('m');"
+        );
+        assert_eq!(
+            rewrite_imports("import * as ns from \"m\"\nns.f()"),
+            "const ns = await import(\"m\");\nns.f()"
+        );
+        assert_eq!(
+            rewrite_imports("import d, * as ns from 'm'"),
+            "const ns = await import('m'), d = ns.default;"
+        );
+        assert_eq!(rewrite_imports("import 'm';"), "await import('m');");
+        assert_eq!(
+            rewrite_imports("import {\n  a,\n} from 'm'; a"),
+            "const { a } = await import('m');\n\n a"
+        );
+    }
+
+    #[test]
+    fn leaves_imports_in_strings_and_comments() {
+        for source in [
+            "const s = \"import x from 'y'\"",
+            "const s = `\nimport x from 'y'`",
+            "// import x from 'y'",
+            "/* import x from 'y' */",
+            "const m = await import('y')",
+            "import.meta.url",
+        ] {
+            assert_eq!(rewrite_imports(source), source);
+        }
+    }
+
+    #[test]
+    fn finds_top_level_declarations() {
+        assert_eq!(
+            declared_names("const a = 1, b = f(1, 2);\nlet c\nvar d = [1, 2]"),
+            ["a", "b", "c", "d"]
+        );
+        assert_eq!(
+            declared_names("const { a, b: [c, d = x], e = 1, ...f } = await g(), [h, , ...i] = j;"),
+            ["a", "c", "d", "e", "f", "h", "i"]
+        );
+        assert_eq!(
+            declared_names("async function f() { const a = 1; }\nclass B extends C {}"),
+            ["f", "B"]
+        );
+        assert_eq!(
+            declared_names("const s = \"const x = 1\"; // let y = 2\nfoo(() => { let z; })"),
+            ["s"]
+        );
+    }
+
+    #[test]
+    fn exports_declarations_after_the_source() {
+        assert_eq!(
+            export_declarations("const a = await f()"),
+            "const a = await f()\n;Object.assign(globalThis, { a });"
+        );
+        assert_eq!(export_declarations("await f()"), "await f()");
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use rquickjs::Error;
+use sourcemap::{DecodedMap, SourceMap};
+
+const INLINE_PREFIX: &str = "//# sourceMappingURL=";
+
+/// Source maps of transpiled modules, keyed by module name
+#[derive(Clone, Default)]
+pub struct SourceMaps(Arc<Mutex<HashMap<String, SourceMap>>>);
+
+impl SourceMaps {
+    pub fn insert(&self, name: impl Into<String>, map: &str) {
+        let name = name.into();
+        match SourceMap::from_slice(map.as_bytes()) {
+            Ok(map) => {
+                self.0.lock().unwrap().insert(name, map);
+            }
+            Err(err) => log::warn!("invalid source map for {}: {}", name, err),
+        }
+    }
+
+    /// Register the inline source map at the end of `code`, if there is one
+    pub fn insert_inline(&self, name: impl Into<String>, code: &str) {
+        let url = match inline_url(code) {
+            Some(url) => url,
+            None => return,
+        };
+
+        let name = name.into();
+        match sourcemap::decode_data_url(url) {
+            Ok(DecodedMap::Regular(map)) => {
+                self.0.lock().unwrap().insert(name, map);
+            }
+            Ok(_) => log::warn!("unsupported source map for {}", name),
+            Err(err) => log::warn!("invalid source map for {}: {}", name, err),
+        }
+    }
+
+    /// The source map of `name` as json
+    pub fn get(&self, name: &str) -> Option<String> {
+        to_json(self.0.lock().unwrap().get(name)?)
+    }
+
+    /// Map a 1-based line (and optionally column) in the transpiled output of a module
+    /// back to the original source
+    pub fn lookup(&self, name: &str, line: u32, col: Option<u32>) -> Option<(u32, u32)> {
+        let maps = self.0.lock().unwrap();
+        let map = maps.get(name)?;
+
+        let line = line.checked_sub(1)?;
+
+        let token = match col {
+            Some(col) => map.lookup_token(line, col.saturating_sub(1))?,
+            None => map.tokens().find(|token| token.get_dst_line() == line)?,
+        };
+
+        Some((token.get_src_line() + 1, token.get_src_col() + 1))
+    }
+
+    pub fn remap_stack(&self, stack: &str) -> String {
+        stack
+            .lines()
+            .map(|frame| self.remap_frame(frame).unwrap_or_else(|| frame.to_string()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn remap_frame(&self, frame: &str) -> Option<String> {
+        let (start, end) = location_range(frame)?;
+        let (file, line, col) = parse_location(&frame[start..end])?;
+        let (line, col) = self.lookup(file, line, col)?;
+
+        Some(format!(
+            "{}{}:{}:{}{}",
+            &frame[..start],
+            file,
+            line,
+            col,
+            &frame[end..]
+        ))
+    }
+
+    /// Rewrite the location and stack of an exception to point into the original sources
+    pub fn remap(&self, err: Error) -> Error {
+        match err {
+            Error::Exception {
+                message,
+                file,
+                line,
+                stack,
+            } => {
+                // The exception only has a line, the column comes from the
+                // frame of the stack at that line
+                let col = column(&stack, &file, line);
+                let line = u32::try_from(line)
+                    .ok()
+                    .and_then(|l| self.lookup(&file, l, col))
+                    .map(|(l, _)| l as _)
+                    .unwrap_or(line);
+
+                Error::Exception {
+                    message,
+                    stack: self.remap_stack(&stack),
+                    file,
+                    line,
+                }
+            }
+            err => err,
+        }
+    }
+}
+
+fn inline_url(code: &str) -> Option<&str> {
+    code.lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix(INLINE_PREFIX))
+}
+
+fn to_json(map: &SourceMap) -> Option<String> {
+    let mut json = Vec::new();
+    map.to_writer(&mut json).ok()?;
+    String::from_utf8(json).ok()
+}
+
+/// The inline source map at the end of `code` as json
+#[cfg(feature = "wasm")]
+pub(crate) fn inline_map(code: &str) -> Option<String> {
+    match sourcemap::decode_data_url(inline_url(code)?) {
+        Ok(DecodedMap::Regular(map)) => to_json(&map),
+        _ => None,
+    }
+}
+
+/// Map of the output of a second transformation back to the original source,
+/// from the map of the first transformation (`first`) and the map of the
+/// second from the output of the first (`second`). Positions of the second
+/// output that do not lead back to the original source are left out
+#[cfg(feature = "wasm")]
+pub(crate) fn compose(first: &str, second: &str) -> Option<String> {
+    let first = SourceMap::from_slice(first.as_bytes()).ok()?;
+    let second = SourceMap::from_slice(second.as_bytes()).ok()?;
+
+    let mut builder = sourcemap::SourceMapBuilder::new(None);
+    for token in second.tokens() {
+        let original = match first.lookup_token(token.get_src_line(), token.get_src_col()) {
+            Some(original) if original.get_dst_line() == token.get_src_line() => original,
+            _ => continue,
+        };
+
+        builder.add(
+            token.get_dst_line(),
+            token.get_dst_col(),
+            original.get_src_line(),
+            original.get_src_col(),
+            original.get_source(),
+            original.get_name().or_else(|| token.get_name()),
+        );
+    }
+
+    for (idx, source) in first.sources().enumerate() {
+        let id = builder.add_source(source);
+        builder.set_source_contents(id, first.get_source_contents(idx as u32));
+    }
+
+    to_json(&builder.into_sourcemap())
+}
+
+/// Range of the `file:line:col` location between the parentheses of a frame
+fn location_range(frame: &str) -> Option<(usize, usize)> {
+    let start = frame.rfind('(')?;
+    let end = frame.rfind(')')?;
+    if end < start {
+        return None;
+    }
+    Some((start + 1, end))
+}
+
+/// Column of the first frame of `stack` located at `line` of `file`
+fn column(stack: &str, file: &str, line: i32) -> Option<u32> {
+    stack.lines().find_map(|frame| {
+        let (start, end) = location_range(frame)?;
+        match parse_location(&frame[start..end])? {
+            (f, l, col) if f == file && i32::try_from(l).ok() == Some(line) => col,
+            _ => None,
+        }
+    })
+}
+
+fn parse_location(location: &str) -> Option<(&str, u32, Option<u32>)> {
+    let (rest, last) = location.rsplit_once(':')?;
+    let last = last.parse().ok()?;
+
+    match rest.rsplit_once(':') {
+        Some((file, line)) => match line.parse() {
+            Ok(line) => Some((file, line, Some(last))),
+            Err(_) => Some((rest, last, None)),
+        },
+        None => Some((rest, last, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sourcemap::SourceMapBuilder;
+
+    /// Map of `main.js` where output line 3 column 5 comes from line 10 column 2
+    fn source_maps() -> SourceMaps {
+        let mut builder = SourceMapBuilder::new(None);
+        builder.add(2, 0, 7, 0, Some("main.ts"), None);
+        builder.add(2, 4, 9, 1, Some("main.ts"), None);
+
+        let mut json = Vec::new();
+        builder.into_sourcemap().to_writer(&mut json).unwrap();
+
+        let maps = SourceMaps::default();
+        maps.insert("main.js", std::str::from_utf8(&json).unwrap());
+        maps
+    }
+
+    #[test]
+    fn parses_locations() {
+        assert_eq!(parse_location("a.js:3:5"), Some(("a.js", 3, Some(5))));
+        assert_eq!(parse_location("a.js:3"), Some(("a.js", 3, None)));
+        assert_eq!(parse_location("a.js"), None);
+    }
+
+    #[test]
+    fn looks_up_columns() {
+        let maps = source_maps();
+        assert_eq!(maps.lookup("main.js", 3, Some(5)), Some((10, 2)));
+        assert_eq!(maps.lookup("main.js", 3, None), Some((8, 1)));
+        assert_eq!(maps.lookup("other.js", 3, None), None);
+    }
+
+    #[test]
+    fn remaps_exceptions_with_the_column_of_their_frame() {
+        let err = SourceMaps::remap(
+            &source_maps(),
+            Error::Exception {
+                message: "boom".into(),
+                file: "main.js".into(),
+                line: 3,
+                stack: "    at f (main.js:3:5)\n    at <eval> (other.js:1:1)".into(),
+            },
+        );
+
+        match err {
+            Error::Exception { line, stack, .. } => {
+                assert_eq!(line, 10);
+                assert_eq!(
+                    stack,
+                    "    at f (main.js:10:2)\n    at <eval> (other.js:1:1)"
+                );
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn composes_maps_of_consecutive_transformations() {
+        // `a.ts` line 5 became line 2 of the first output, which became line 0
+        let mut builder = SourceMapBuilder::new(None);
+        builder.add(2, 0, 5, 0, Some("a.ts"), None);
+        builder.add(2, 6, 5, 10, Some("a.ts"), Some("value"));
+        builder.add(3, 0, 6, 0, Some("a.ts"), None);
+        let first = to_json(&builder.into_sourcemap()).unwrap();
+
+        let mut builder = SourceMapBuilder::new(None);
+        builder.add(0, 0, 2, 0, Some("a.js"), None);
+        builder.add(0, 4, 2, 6, Some("a.js"), None);
+        builder.add(1, 0, 9, 0, Some("a.js"), None);
+        let second = to_json(&builder.into_sourcemap()).unwrap();
+
+        let maps = SourceMaps::default();
+        maps.insert("a.js", &compose(&first, &second).unwrap());
+
+        assert_eq!(maps.lookup("a.js", 1, Some(1)), Some((6, 1)));
+        assert_eq!(maps.lookup("a.js", 1, Some(5)), Some((6, 11)));
+        // Line 9 of the first output is not mapped
+        assert_eq!(maps.lookup("a.js", 2, None), None);
+    }
+}
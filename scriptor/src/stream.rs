@@ -6,6 +6,8 @@ use futures_lite::StreamExt;
 use rquickjs::IntoJs;
 use tokio::sync::Mutex;
 
+use crate::error::Settled;
+
 #[derive(IntoJs)]
 pub struct Next<T> {
     done: bool,
@@ -30,26 +32,31 @@ impl<S> JsStream<S> {
     }
 }
 
-impl<S> JsStream<S>
+impl<S, T, E> JsStream<S>
 where
-    S: Stream + std::marker::Unpin + Send + 'static,
-    for<'js> S::Item: IntoJs<'js>,
-    S::Item: Send,
+    S: Stream<Item = std::result::Result<T, E>> + std::marker::Unpin + Send + 'static,
+    for<'js> T: IntoJs<'js>,
+    for<'js> E: IntoJs<'js>,
+    T: Send,
+    E: Send,
 {
-    pub fn next(&self) -> BoxFuture<'static, Next<S::Item>> {
+    /// The next item. A failed item rejects with its error, and the items
+    /// after it can still be read
+    pub fn next(&self) -> BoxFuture<'static, Settled<Next<T>, E>> {
         let stream = self.s.clone();
         Box::pin(async move {
             let mut stream = stream.lock().await;
 
             match stream.next().await {
-                Some(next) => Next {
+                Some(Ok(next)) => Settled::Resolved(Next {
                     done: false,
                     value: Some(next),
-                },
-                None => Next {
+                }),
+                Some(Err(err)) => Settled::Rejected(err),
+                None => Settled::Resolved(Next {
                     done: true,
                     value: None,
-                },
+                }),
             }
         })
     }
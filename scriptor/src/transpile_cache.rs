@@ -0,0 +1,123 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::utils::{sha256_hex, write_atomic};
+
+pub struct Transpiled {
+    pub code: String,
+    pub map: Option<String>,
+}
+
+/// On disk cache of transpiled modules.
+///
+/// Entries are keyed by the identity of the loader and a hash of the source, so
+/// editing a file or updating a loader never hits a stale entry.
+#[derive(Clone, Debug)]
+pub struct TranspileCache {
+    root: PathBuf,
+}
+
+impl TranspileCache {
+    pub fn new(root: impl Into<PathBuf>) -> TranspileCache {
+        TranspileCache { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn entry(&self, loader: &str, source: &str) -> PathBuf {
+        let key = hash(&[loader.as_bytes(), source.as_bytes()]);
+        self.root.join(&key[..2]).join(&key[2..])
+    }
+
+    pub fn get(&self, loader: &str, source: &str) -> Option<Transpiled> {
+        let entry = self.entry(loader, source);
+
+        let code = std::fs::read_to_string(entry.with_extension("js")).ok()?;
+        let map = std::fs::read_to_string(entry.with_extension("js.map")).ok();
+
+        Some(Transpiled { code, map })
+    }
+
+    pub fn set(&self, loader: &str, source: &str, code: &str, map: Option<&str>) {
+        let entry = self.entry(loader, source);
+        if let Err(err) = write_entry(&entry, code, map) {
+            log::warn!("could not write transpile cache {:?}: {}", entry, err);
+        }
+    }
+
+    /// Remove every cached module
+    pub fn clear(&self) -> io::Result<()> {
+        if self.root.exists() {
+            std::fs::remove_dir_all(&self.root)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_entry(entry: &Path, code: &str, map: Option<&str>) -> io::Result<()> {
+    if let Some(parent) = entry.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if let Some(map) = map {
+        write_atomic(&entry.with_extension("js.map"), map)?;
+    }
+
+    // The code is written last, as its presence is what marks an entry as complete
+    write_atomic(&entry.with_extension("js"), code)
+}
+
+/// Hex encoded sha256 of `parts`. Every part is prefixed with its length, so
+/// moving bytes from one part to the next changes the hash
+pub fn hash(parts: &[&[u8]]) -> String {
+    let mut bytes = Vec::with_capacity(parts.iter().map(|part| part.len() + 8).sum());
+    for part in parts {
+        bytes.extend_from_slice(&(part.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(part);
+    }
+
+    sha256_hex(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn cache() -> (TempDir, TranspileCache) {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TranspileCache::new(dir.path());
+        (dir, cache)
+    }
+
+    #[test]
+    fn round_trips_entries() {
+        let (_dir, cache) = cache();
+        assert!(cache.get("ts", "let a: number").is_none());
+
+        cache.set("ts", "let a: number", "let a", Some("{}"));
+
+        let entry = cache.get("ts", "let a: number").unwrap();
+        assert_eq!(entry.code, "let a");
+        assert_eq!(entry.map.as_deref(), Some("{}"));
+    }
+
+    #[test]
+    fn keys_entries_by_loader_and_source() {
+        let (_dir, cache) = cache();
+        cache.set("ts", "source", "from ts", None);
+
+        assert!(cache.get("coffee", "source").is_none());
+        assert!(cache.get("ts", "source2").is_none());
+        assert!(cache.get("ts", "source").unwrap().map.is_none());
+    }
+
+    #[test]
+    fn hash_separates_parts() {
+        assert_ne!(hash(&[b"ab", b"c"]), hash(&[b"a", b"bc"]));
+    }
+}
@@ -1,20 +1,66 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 use rquickjs::{Error, Loader, Module};
 
+use crate::{SourceMaps, TranspileCache};
+
 use swc::{
     common::{
         errors::{ColorConfig, Handler},
         FileName, SourceMap,
     },
-    config::{JscConfig, Options},
+    config::{JscConfig, Options, SourceMapsConfig},
     ecmascript::ast::EsVersion,
     // sourcemap::SourceMap,
 };
 
+const LOADER_ID: &str = concat!("typescript@", env!("CARGO_PKG_VERSION"));
+
+/// File extensions handled by the typescript loader
+pub const TYPESCRIPT_EXTENSIONS: &[&str] = &["ts", "tsx", "mts"];
+
+pub fn is_typescript(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .map(|ext| TYPESCRIPT_EXTENSIONS.iter().any(|m| ext == *m))
+        .unwrap_or_default()
+}
+
+fn is_tsx(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .map(|ext| ext == "tsx")
+        .unwrap_or_default()
+}
+
+fn options(tsx: bool) -> Options {
+    Options {
+        config: swc::config::Config {
+            jsc: JscConfig {
+                target: EsVersion::Es2017.into(),
+                external_helpers: false.into(),
+                syntax: Some(swc_ecma_parser::Syntax::Typescript(
+                    swc_ecma_parser::TsConfig {
+                        tsx,
+
+                        ..Default::default()
+                    },
+                )),
+
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        source_maps: Some(SourceMapsConfig::Bool(true)),
+        ..Default::default()
+    }
+}
+
 pub struct TypescriptFileLoader {
     sm: Arc<SourceMap>,
     handler: Arc<Handler>,
+    source_maps: SourceMaps,
+    cache: Option<TranspileCache>,
 }
 
 // impl fmt::Debug for TypescriptFileLoader {
@@ -23,8 +69,9 @@ pub struct TypescriptFileLoader {
 //     }
 // }
 
-impl Default for TypescriptFileLoader {
-    fn default() -> TypescriptFileLoader {
+impl TypescriptFileLoader {
+    /// Create a loader registering the source map of every module it loads in `source_maps`
+    pub fn new(source_maps: SourceMaps) -> TypescriptFileLoader {
         let sm = Arc::new(SourceMap::default());
 
         let handler = Arc::new(Handler::with_tty_emitter(
@@ -34,7 +81,22 @@ impl Default for TypescriptFileLoader {
             Some(sm.clone()),
         ));
 
-        TypescriptFileLoader { sm, handler }
+        TypescriptFileLoader {
+            sm,
+            handler,
+            source_maps,
+            cache: None,
+        }
+    }
+
+    pub fn set_cache(&mut self, cache: Option<TranspileCache>) {
+        self.cache = cache;
+    }
+}
+
+impl Default for TypescriptFileLoader {
+    fn default() -> TypescriptFileLoader {
+        TypescriptFileLoader::new(SourceMaps::default())
     }
 }
 
@@ -43,49 +105,58 @@ impl Loader for TypescriptFileLoader {
         &mut self,
         ctx: rquickjs::Ctx<'js>,
         path: &str,
-    ) -> rquickjs::Result<rquickjs::Module<'js, rquickjs::Loaded>> {
+    ) -> rquickjs::Result<rquickjs::Module<'js, rquickjs::Loaded<()>>> {
+        if !is_typescript(path) {
+            return Err(Error::new_loading(path));
+        }
+
         let source = std::fs::read_to_string(&path)?;
+        let source = crate::assets::rewrite_import_attributes(&source).into_owned();
+
+        let tsx = is_tsx(path);
+        let id = format!("{}{}", LOADER_ID, if tsx { "+tsx" } else { "" });
+
+        if let Some(cached) = self.cache.as_ref().and_then(|m| m.get(&id, &source)) {
+            if let Some(map) = &cached.map {
+                self.source_maps.insert(path, map);
+            }
+            return Ok(Module::new(ctx, path, cached.code)?.into_loaded());
+        }
 
         let c = swc::Compiler::new(self.sm.clone());
 
         let fm = self
             .sm
-            .new_source_file(FileName::Custom(path.into()), source);
+            .new_source_file(FileName::Custom(path.into()), source.clone());
 
         let output = c
-            .process_js_file(
-                fm,
-                &self.handler,
-                &Options {
-                    config: swc::config::Config {
-                        jsc: JscConfig {
-                            target: EsVersion::Es2017.into(),
-                            external_helpers: false.into(),
-                            syntax: Some(swc_ecma_parser::Syntax::Typescript(
-                                swc_ecma_parser::TsConfig {
-                                    tsx: true,
-
-                                    ..Default::default()
-                                },
-                            )),
-
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                },
-            )
+            .process_js_file(fm, &self.handler, &options(tsx))
             .map_err(|err| Error::Loading {
                 name: path.to_string(),
                 message: Some(err.to_string()),
             })?;
 
+        if let Some(map) = &output.map {
+            self.source_maps.insert(path, map);
+        }
+
+        if let Some(cache) = &self.cache {
+            cache.set(&id, &source, &output.code, output.map.as_deref());
+        }
+
         Ok(Module::new(ctx, path, output.code)?.into_loaded())
     }
 }
 
 pub fn compile(name: &str, source: impl ToString) -> Result<String, Error> {
+    transpile(name, source).map(|(code, _)| code)
+}
+
+/// Javascript compiled from the typescript `source`, and its source map
+pub(crate) fn transpile(
+    name: &str,
+    source: impl ToString,
+) -> Result<(String, Option<String>), Error> {
     let cm = Arc::new(SourceMap::default());
 
     let fm = cm.new_source_file(FileName::Custom(name.into()), source.to_string());
@@ -100,33 +171,11 @@ pub fn compile(name: &str, source: impl ToString) -> Result<String, Error> {
     let c = swc::Compiler::new(cm.clone());
 
     let output = c
-        .process_js_file(
-            fm,
-            &handler,
-            &Options {
-                config: swc::config::Config {
-                    jsc: JscConfig {
-                        target: EsVersion::Es2017.into(),
-                        external_helpers: false.into(),
-                        syntax: Some(swc_ecma_parser::Syntax::Typescript(
-                            swc_ecma_parser::TsConfig {
-                                tsx: true,
-
-                                ..Default::default()
-                            },
-                        )),
-
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                },
-                ..Default::default()
-            },
-        )
+        .process_js_file(fm, &handler, &options(is_tsx(name)))
         .map_err(|err| Error::Loading {
             name: name.to_string(),
             message: Some(err.to_string()),
         })?;
 
-    Ok(output.code)
+    Ok((output.code, output.map))
 }
@@ -1,7 +1,6 @@
 use rquickjs::{
     BuiltinLoader, BuiltinResolver, Bundle, Context, Ctx, FileResolver, Function, IntoJs, Loader,
     ModuleDef, ModuleLoader, NativeLoader, Promise, Resolver, Result, Runtime, Script,
-    ScriptLoader,
 };
 use std::{
     cell::RefCell,
@@ -11,16 +10,29 @@ use std::{
 };
 
 #[cfg(feature = "os")]
-static MAIN: &'static str = include_str!("../lib/main.js");
+pub(crate) static MAIN: &'static str = include_str!("../lib/main.js");
 
 use crate::{
+    assets::{AssetKind, AssetLoader, ScriptFileLoader},
     bundle_module::{BundleModule, BundleModuleCol, BundleModuleImpl},
+    bundler::{SharedResolver, Sources},
+    bytecode::{compile, BytecodeLoader},
+    process::{Exit, Process},
     user_module::{IntoUserModule, UserModule},
     utils::Either,
+    watch::{LoadedFiles, TrackingLoader},
+    BundleFormat, ImportMap, ImportMapResolver, NodeResolver, ScriptBundle, SourceMaps,
+    TranspileCache, IMPORT_MAP,
 };
 
+#[cfg(feature = "http")]
+use crate::remote::{RemoteModules, LOCKFILE};
+
 #[cfg(feature = "wasm")]
-use crate::wasm_loader::{open_path, WasmConfig, WasmLoaders};
+use crate::wasm_loader::{open_path, Sandbox, WasmConfig, WasmLoaders};
+
+#[cfg(feature = "typescript")]
+use crate::typescript_loader::{TypescriptFileLoader, TYPESCRIPT_EXTENSIONS};
 
 use super::bundle::*;
 
@@ -29,12 +41,43 @@ pub struct DirConfig {
     config: PathBuf,
 }
 
+impl DirConfig {
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache
+    }
+
+    pub fn config_dir(&self) -> &Path {
+        &self.config
+    }
+
+    /// Directory wasm loaders are installed in
+    pub fn loaders_dir(&self) -> PathBuf {
+        self.config.join("loaders")
+    }
+
+    /// Directories below `root`, or the platform specific defaults, created if missing
+    pub async fn new(root: Option<PathBuf>) -> std::io::Result<DirConfig> {
+        VmBuilder::get_dir_config(root).await
+    }
+}
+
 #[derive(Default)]
 pub struct VmBuilder {
     modules: Vec<Box<dyn UserModule + Send>>,
     bundles: Vec<Box<dyn BundleModule + Send>>,
     cwd: Option<PathBuf>,
     root: Option<PathBuf>,
+    argv: Option<Vec<String>>,
+    no_transpile_cache: bool,
+    no_bytecode: bool,
+    import_map: ImportMap,
+    import_map_file: Option<PathBuf>,
+    #[cfg(feature = "http")]
+    offline: bool,
+    #[cfg(feature = "http")]
+    lockfile: Option<PathBuf>,
+    #[cfg(feature = "wasm")]
+    sandbox: Sandbox,
 }
 
 impl VmBuilder {
@@ -64,6 +107,72 @@ impl VmBuilder {
         self
     }
 
+    /// Cache the output of typescript and wasm loaders on disk. Enabled by default
+    pub fn transpile_cache(&mut self, enabled: bool) -> &mut Self {
+        self.no_transpile_cache = !enabled;
+        self
+    }
+
+    /// Load modules from bytecode written by `Vm::precompile` while it is
+    /// newer than their source. Enabled by default
+    pub fn load_bytecode(&mut self, enabled: bool) -> &mut Self {
+        self.no_bytecode = !enabled;
+        self
+    }
+
+    /// Add the entries of `map` on top of the project import map. Relative
+    /// targets of a map without a base are resolved against the working directory
+    pub fn import_map(&mut self, map: ImportMap) -> &mut Self {
+        self.import_map.extend(map);
+        self
+    }
+
+    /// Map a bare specifier, or every specifier starting with a prefix ending
+    /// in `/`, to a path or to another specifier such as a builtin module
+    pub fn alias(&mut self, specifier: impl Into<String>, target: impl Into<String>) -> &mut Self {
+        self.import_map.insert(specifier, target);
+        self
+    }
+
+    /// Read the project import map from `path` rather than `import_map.json`
+    /// in the working directory
+    pub fn import_map_file(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.import_map_file = Some(path.into());
+        self
+    }
+
+    /// Only load remote modules already in the cache, never fetch them
+    #[cfg(feature = "http")]
+    pub fn offline(&mut self, offline: bool) -> &mut Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Record the hashes of remote modules in `path` rather than
+    /// `scriptor.lock` in the working directory
+    #[cfg(feature = "http")]
+    pub fn lockfile(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.lockfile = Some(path.into());
+        self
+    }
+
+    /// Restrict the resources available to wasm loaders
+    #[cfg(feature = "wasm")]
+    pub fn sandbox(&mut self, sandbox: Sandbox) -> &mut Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Set `process.argv`. Defaults to the arguments of the host process
+    pub fn argv<I, S>(&mut self, argv: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.argv = Some(argv.into_iter().map(Into::into).collect());
+        self
+    }
+
     pub async fn build(self) -> Result<Vm> {
         self.build_with(|_| Ok(())).await
     }
@@ -73,7 +182,7 @@ impl VmBuilder {
             tokio::fs::create_dir_all(&cfg.cache).await?;
         }
 
-        let loaders = cfg.config.join("loaders");
+        let loaders = cfg.loaders_dir();
         if !loaders.exists() {
             tokio::fs::create_dir_all(loaders).await?;
         }
@@ -82,13 +191,19 @@ impl VmBuilder {
     }
 
     #[cfg(feature = "wasm")]
-    async fn get_wasm_loader(root: &DirConfig) -> anyhow::Result<Option<WasmLoaders>> {
-        let loaders = root.config.join("loaders");
+    async fn get_wasm_loader(
+        root: &DirConfig,
+        cwd: &Path,
+        sandbox: Sandbox,
+    ) -> anyhow::Result<Option<WasmLoaders>> {
+        let loaders = root.loaders_dir();
 
         let loader = open_path(WasmConfig {
             loaders: &loaders,
             config: &root.config,
             cache: Some(&root.cache),
+            project: Some(cwd),
+            sandbox,
         })
         .await?;
 
@@ -98,6 +213,7 @@ impl VmBuilder {
     #[cfg(not(feature = "wasm"))]
     async fn get_wasm_loader(
         root: &DirConfig,
+        cwd: &Path,
     ) -> std::result::Result<Option<BuiltinLoader>, std::convert::Infallible> {
         Ok(None)
     }
@@ -146,29 +262,141 @@ impl VmBuilder {
             .with_path(&cwd.as_os_str().to_string_lossy())
             .with_native();
 
-        let script_loader = ScriptLoader::default();
+        // Extensions besides js and mjs that imports may leave out
+        #[allow(unused_mut)]
+        let mut extensions = vec!["json".to_string(), "txt".to_string()];
+
+        #[cfg(feature = "typescript")]
+        extensions.extend(TYPESCRIPT_EXTENSIONS.iter().map(|ext| ext.to_string()));
+
+        // The project import map, with the entries given to the builder on top
+        let mut import_map = ImportMap::new(&cwd);
+        let import_map_path = match &self.import_map_file {
+            Some(path) => Some(cwd.join(path)),
+            None => Some(cwd.join(IMPORT_MAP)).filter(|path| path.exists()),
+        };
+        if let Some(path) = import_map_path {
+            import_map.extend(ImportMap::from_file(path)?);
+        }
+        import_map.extend(self.import_map);
 
         let dir_cfg = VmBuilder::get_dir_config(self.root).await?;
 
-        let wasm_loader = VmBuilder::get_wasm_loader(&dir_cfg)
+        #[allow(unused_variables)]
+        let transpile_cache = if self.no_transpile_cache {
+            None
+        } else {
+            Some(TranspileCache::new(dir_cfg.cache.join("modules")))
+        };
+
+        let script_loader = ScriptFileLoader::default();
+
+        let source_maps = SourceMaps::default();
+
+        #[cfg(feature = "typescript")]
+        let script_loader = {
+            let mut typescript = TypescriptFileLoader::new(source_maps.clone());
+            typescript.set_cache(transpile_cache.clone());
+            (typescript, script_loader)
+        };
+
+        #[cfg(feature = "http")]
+        let remote = {
+            let lockfile = cwd.join(self.lockfile.unwrap_or_else(|| PathBuf::from(LOCKFILE)));
+            let mut remote = RemoteModules::new(dir_cfg.cache.join("remote"), lockfile)?;
+            remote.set_offline(self.offline);
+            remote.set_source_maps(source_maps.clone());
+            remote
+        };
+
+        // Remote modules are transpiled by their own loader
+        #[cfg(feature = "http")]
+        let script_loader = (remote.clone(), script_loader);
+
+        #[cfg(feature = "wasm")]
+        let mut wasm_loader = VmBuilder::get_wasm_loader(&dir_cfg, &cwd, self.sandbox)
+            .await
+            .map_err(throw!())?;
+
+        #[cfg(not(feature = "wasm"))]
+        let wasm_loader = VmBuilder::get_wasm_loader(&dir_cfg, &cwd)
             .await
             .map_err(throw!())?;
 
         #[cfg(feature = "wasm")]
-        if let Some(loader) = &wasm_loader {
-            for ext in loader.extensions() {
-                let mut string = String::from("{}.");
-                string.push_str(ext);
-                script_resolver.add_pattern(string);
-            }
+        if let Some(loader) = &mut wasm_loader {
+            loader.set_source_maps(source_maps.clone());
+            loader.set_cache(transpile_cache.clone());
+
+            // Plain wasm modules can be imported as well
+            extensions.push(crate::wasm_module::EXTENSION.to_string());
+            extensions.extend(loader.extensions());
+        }
+
+        for ext in &extensions {
+            script_resolver.add_pattern(format!("{{}}.{}", ext));
         }
 
         let rt = Runtime::new()?;
         let ctx = Context::full(&rt)?;
 
-        rt.spawn_executor(rquickjs::Tokio);
+        let executor = rt.spawn_executor(rquickjs::Tokio);
+
+        // Packages in node_modules are looked up after files relative to cwd
+        let script_resolver = (script_resolver, NodeResolver::new(&cwd));
+
+        // Loaders get to resolve bare specifiers before the file resolver
+        #[cfg(feature = "wasm")]
+        let script_resolver = match &wasm_loader {
+            Some(wasm) => Either::Left((wasm.clone(), script_resolver)),
+            None => Either::Right(script_resolver),
+        };
+
+        let sources = Sources {
+            cwd: cwd.clone(),
+            source_maps: source_maps.clone(),
+            #[cfg(feature = "wasm")]
+            wasm: wasm_loader.clone(),
+            #[cfg(feature = "http")]
+            remote: remote.clone(),
+        };
+
+        // Urls, and paths relative to remote modules, are never files on disk
+        #[cfg(feature = "http")]
+        let script_resolver = (remote, script_resolver);
+
+        // Bundled modules take precedence over the files they were bundled from
+        let bundles = BundleModuleCol(Rc::new(RefCell::new(self.bundles)));
+
+        let resolver = (
+            resolver,
+            UTIL,
+            PIPE,
+            TASKS,
+            bundles.clone(),
+            AssetLoader,
+            script_resolver,
+        );
+        // Bytecode compiled by a vm configured differently is not loaded
+        #[allow(unused_mut)]
+        let mut config = vec![
+            env!("CARGO_PKG_VERSION").to_string(),
+            format!("{:?}", import_map),
+            format!("typescript: {}", cfg!(feature = "typescript")),
+        ];
+
+        #[cfg(feature = "wasm")]
+        if let Some(wasm) = &wasm_loader {
+            config.push(wasm.fingerprint().to_string());
+        }
+
+        let bytecode_config = crate::transpile_cache::hash(
+            &config
+                .iter()
+                .map(|part| part.as_bytes())
+                .collect::<Vec<_>>(),
+        );
 
-        let resolver = (resolver, UTIL, PIPE, TASKS, script_resolver);
         let loader = match wasm_loader {
             Some(wasm) => Either::Left((
                 loader,
@@ -176,6 +404,7 @@ impl VmBuilder {
                 PIPE,
                 TASKS,
                 wasm,
+                AssetLoader,
                 script_loader,
                 NativeLoader::default(),
             )),
@@ -184,19 +413,49 @@ impl VmBuilder {
                 UTIL,
                 PIPE,
                 TASKS,
+                AssetLoader,
                 script_loader,
                 NativeLoader::default(),
             )),
         };
 
-        if !self.bundles.is_empty() {
-            let col = BundleModuleCol(Rc::new(RefCell::new(self.bundles)));
-            rt.set_loader((resolver, col.clone()), (col.clone(), loader));
+        let loader = if self.no_bytecode {
+            Either::Right(loader)
         } else {
-            rt.set_loader(resolver, loader);
+            Either::Left((
+                BytecodeLoader::new(&bytecode_config, source_maps.clone()),
+                loader,
+            ))
+        };
+
+        let files = LoadedFiles::default();
+        let loader = TrackingLoader::new(loader, files.clone(), &cwd);
+
+        // The import map goes in front of everything else, so aliases apply to
+        // builtins, bundles and loaded files alike
+        let mut import_map = ImportMapResolver::new(import_map, ());
+        for ext in &extensions {
+            import_map.add_extension(ext.as_str());
         }
 
+        // Shared with the bundler, which resolves imports the same way
+        let resolver = SharedResolver::new(import_map.wrap(resolver));
+        rt.set_loader(resolver.clone(), (bundles, loader));
+
+        let exit = Exit::default();
+
+        rt.set_interrupt_handler(Some(Box::new({
+            let exit = exit.clone();
+            move || exit.get().is_some()
+        })));
+
+        let process = match self.argv {
+            Some(argv) => Process::new(argv),
+            None => Process::default(),
+        };
+
         ctx.with(|ctx| crate::global::init(ctx))?;
+        ctx.with(|ctx| crate::process::init(ctx, process, exit.clone(), cwd.clone()))?;
 
         ctx.with(config)?;
 
@@ -204,6 +463,13 @@ impl VmBuilder {
             rt,
             ctx,
             dirs: dir_cfg,
+            exit,
+            source_maps,
+            files,
+            resolver,
+            sources,
+            executor,
+            bytecode_config,
         })
     }
 }
@@ -212,6 +478,15 @@ pub struct Vm {
     rt: Runtime,
     ctx: Context,
     dirs: DirConfig,
+    exit: Exit,
+    source_maps: SourceMaps,
+    files: LoadedFiles,
+    resolver: SharedResolver,
+    sources: Sources,
+    /// Runs the futures of async functions called by scripts
+    executor: tokio::task::JoinHandle<()>,
+    /// Identifies the configuration bytecode depends on
+    bytecode_config: String,
 }
 
 impl Vm {
@@ -223,6 +498,11 @@ impl Vm {
         &self.dirs
     }
 
+    /// Working directory scripts are resolved against
+    pub fn cwd(&self) -> &Path {
+        &self.sources.cwd
+    }
+
     pub async fn new(work_path: impl AsRef<Path>) -> Result<Vm> {
         let mut builder = VmBuilder::default();
 
@@ -243,35 +523,152 @@ impl Vm {
         self.ctx.with(func)
     }
 
-    pub async fn run_main<A>(&mut self, path: impl AsRef<Path>, args: A) -> Result<()>
+    /// Bundle the module at `entry` and everything it imports, resolved and
+    /// transpiled the way this vm would
+    pub fn bundle(&self, entry: impl AsRef<Path>, format: BundleFormat) -> Result<ScriptBundle> {
+        let mut resolver = self.resolver.clone();
+        let bundle = self.ctx.with(|ctx| {
+            crate::bundler::bundle(ctx, &mut resolver, &self.sources, entry.as_ref())
+        })?;
+
+        match format {
+            BundleFormat::Source => Ok(bundle),
+            BundleFormat::Bytecode => bundle.into_bytecode(),
+        }
+    }
+
+    /// Compile the module at `entry`, and every file it imports, to bytecode
+    /// stored next to the sources. Returns the paths written
+    pub fn precompile(&self, entry: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut resolver = self.resolver.clone();
+        let graph = self
+            .ctx
+            .with(|ctx| crate::bundler::graph(ctx, &mut resolver, &self.sources, entry.as_ref()))?;
+
+        // Remote modules have no file to put the bytecode next to, and assets
+        // are cheaper to load from their source
+        let files = graph
+            .iter()
+            .filter(|m| {
+                Path::new(&m.name).is_file() && AssetKind::from_extension(&m.name).is_none()
+            })
+            .map(|m| (m.name.as_str(), m.source.as_str()));
+
+        compile(files)?
+            .into_iter()
+            .map(|(name, bytecode)| {
+                let map = self.source_maps.get(&name);
+                Ok(crate::bytecode::write(
+                    Path::new(&name),
+                    &bytecode,
+                    &self.bytecode_config,
+                    map.as_deref(),
+                )?)
+            })
+            .collect()
+    }
+
+    /// Files behind the modules loaded so far
+    pub fn loaded_files(&self) -> LoadedFiles {
+        self.files.clone()
+    }
+
+    /// Stop the vm. Running scripts are interrupted, and the async calls they
+    /// left pending are cancelled before this returns
+    pub async fn shutdown(self) {
+        if self.exit.get().is_none() {
+            self.exit.set(0);
+        }
+
+        self.executor.abort();
+        // Only fails as it was cancelled
+        let _ = self.executor.await;
+    }
+
+    /// The exit code passed to `process.exit`, if a script called it
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit.get()
+    }
+
+    /// Run the main function of the module at `path`, returning the exit code of the script
+    pub async fn run_main<A>(&mut self, path: impl AsRef<Path>, args: A) -> Result<i32>
     where
         for<'js> A: IntoJs<'js>,
     {
         let idle = self.rt.idle();
 
         #[cfg(not(feature = "os"))]
-        let source = tokio::fs::read_to_string(path).await?;
+        let source = tokio::fs::read_to_string(path.as_ref()).await?;
 
         #[cfg(all(feature = "typescript", not(feature = "os")))]
-        let source = crate::compile("main", source).map_err(throw!())?;
-
-        self.ctx
-            .with(|ctx| {
-                cfg_if::cfg_if! {
-                    if #[cfg(not(feature = "os"))] {
-                        let module = ctx.compile("main", source)?;
-                        let main: Function = module.get("main")?;
-                        main.call::<_, Promise<()>>((args,))
-                    } else {
-                        let module = ctx.compile("main", MAIN)?;
-                        let main: Function = module.get("main")?;
-                        let path = path.as_ref().to_string_lossy().to_string();
-                        main.call::<_, Promise<()>>((path, args))
-                    }
+        let source = if crate::is_typescript(&path) {
+            crate::compile(&path.as_ref().to_string_lossy(), source)?
+        } else {
+            source
+        };
+
+        let promise = self.ctx.with(|ctx| {
+            cfg_if::cfg_if! {
+                if #[cfg(not(feature = "os"))] {
+                    let module = ctx.compile("main", source)?;
+                    let main: Function = module.get("main")?;
+                    main.call::<_, Promise<()>>((args,))
+                } else {
+                    let module = ctx.compile("main", MAIN)?;
+                    let main: Function = module.get("main")?;
+                    let path = path.as_ref().to_string_lossy().to_string();
+                    main.call::<_, Promise<()>>((path, args))
                 }
-            })?
-            .await?;
+            }
+        });
+
+        let ret = match promise {
+            Ok(promise) => promise.await,
+            Err(err) => Err(err),
+        };
+
+        self.complete(ret, idle).await
+    }
+
+    /// Evaluate `source` as a global script, returning the exit code of the script
+    #[cfg(feature = "os")]
+    pub async fn eval(&mut self, source: impl Into<String>) -> Result<i32> {
+        let idle = self.rt.idle();
+
+        let source = source.into();
+
+        let promise = self.ctx.with(|ctx| {
+            let module = ctx.compile("main", MAIN)?;
+            let evaluate: Function = module.get("evaluate")?;
+            evaluate.call::<_, Promise<()>>((source,))
+        });
+
+        let ret = match promise {
+            Ok(promise) => promise.await,
+            Err(err) => Err(err),
+        };
+
+        self.complete(ret, idle).await
+    }
+
+    async fn complete<F: std::future::Future>(&self, ret: Result<()>, idle: F) -> Result<i32> {
+        let ret = match ret {
+            Ok(_) => self.finish(idle).await,
+            Err(err) if self.exit.get().is_some() => {
+                // Let pending writes flush before reporting the exit
+                idle.await;
+                Err(err)
+            }
+            Err(err) => Err(err),
+        };
+
+        match self.exit.get() {
+            Some(code) => Ok(code),
+            None => ret.map(|_| 0).map_err(|err| self.source_maps.remap(err)),
+        }
+    }
 
+    pub(crate) async fn finish<F: std::future::Future>(&self, idle: F) -> Result<()> {
         if self.rt.is_job_pending() {
             while self.rt.is_job_pending() {
                 self.rt.execute_pending_job()?;
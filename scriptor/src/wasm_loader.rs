@@ -1,38 +1,214 @@
-use std::path::{Path, PathBuf};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 // use anyhow::Result;
-use rquickjs::{Loader, Module as JsModule};
-use wasmtime::{Config, Engine, Instance, Linker, Module, Store};
+use rquickjs::{Error, Loader, Module as JsModule, Resolver};
+use serde::{Deserialize, Serialize};
+use wasi_common::{dir::DirCaps, file::FileCaps};
+use wasmtime::{
+    Config, Engine, Instance, Linker, Module, ResourceLimiter, Store, StoreLimits,
+    StoreLimitsBuilder, Trap, TrapCode,
+};
 
-use self::loader::LoaderData;
+use crate::{source_map, transpile_cache::hash, SourceMaps, TranspileCache};
 
 wit_bindgen_wasmtime::import!("../loader.wit");
+wit_bindgen_wasmtime::import!("../loader-v2.wit");
+
+type V1Context = Context<(), loader::LoaderData>;
+type V2Context = Context<(), loader_v2::LoaderV2Data>;
+
+enum Exports {
+    V1 {
+        store: Store<V1Context>,
+        exports: loader::Loader<V1Context>,
+    },
+    V2 {
+        store: Store<V2Context>,
+        exports: loader_v2::LoaderV2<V2Context>,
+    },
+}
+
+struct Transformed {
+    code: String,
+    map: Option<String>,
+}
+
 pub struct WasmLoader {
-    store: Store<Context<(), LoaderData>>,
-    exports: loader::Loader<Context<(), LoaderData>>,
+    exports: Exports,
     exts: Vec<String>,
+    resolver: bool,
+    id: String,
+    name: String,
+    config: String,
+    limits: Limits,
 }
 
 impl WasmLoader {
-    fn load<'js>(
+    /// Extensions of the files transformed by the loader
+    pub fn extensions(&self) -> &[String] {
+        &self.exts
+    }
+
+    /// Version of the loader interface implemented by the loader
+    pub fn version(&self) -> u32 {
+        match self.exports {
+            Exports::V1 { .. } => 1,
+            Exports::V2 { .. } => 2,
+        }
+    }
+
+    /// Whether the loader resolves bare specifiers
+    pub fn is_resolver(&self) -> bool {
+        self.resolver
+    }
+
+    /// Transform `source`, or `None` if the loader declined it
+    fn transform(&mut self, path: &str, source: &str) -> rquickjs::Result<Option<Transformed>> {
+        let limits = self.limits;
+        let name = &self.name;
+
+        match &mut self.exports {
+            Exports::V1 { store, exports } => {
+                limits.arm(store).map_err(throw!())?;
+                match exports
+                    .transform(&mut *store, source)
+                    .map_err(|err| trap_error(&subject(name), "transform", limits, store, err))?
+                {
+                    loader::Compilation::Success(code) => Ok(Some(Transformed { code, map: None })),
+                    loader::Compilation::Failure(err) => {
+                        Err(Error::new_loading_message(path, err))
+                    }
+                }
+            }
+            Exports::V2 { store, exports } => {
+                let options = loader_v2::TransformOptions {
+                    path,
+                    config: &self.config,
+                };
+
+                limits.arm(store).map_err(throw!())?;
+                match exports
+                    .transform(&mut *store, source, options)
+                    .map_err(|err| trap_error(&subject(name), "transform", limits, store, err))?
+                {
+                    loader_v2::Compilation::Success(output) => {
+                        report(&output.diagnostics);
+                        Ok(Some(Transformed {
+                            code: output.code,
+                            map: output.source_map,
+                        }))
+                    }
+                    loader_v2::Compilation::Declined => Ok(None),
+                    loader_v2::Compilation::Failure(diagnostics) => {
+                        report(&diagnostics);
+                        Err(Error::new_loading_message(
+                            path,
+                            format_diagnostics(&diagnostics),
+                        ))
+                    }
+                }
+            }
+        }
+    }
+
+    fn resolve(&mut self, base: &str, name: &str) -> rquickjs::Result<Option<String>> {
+        let limits = self.limits;
+        let loader = &self.name;
+
+        match &mut self.exports {
+            Exports::V2 { store, exports } if self.resolver => {
+                limits.arm(store).map_err(throw!())?;
+                exports
+                    .resolve(&mut *store, name, base)
+                    .map_err(|err| trap_error(&subject(loader), "resolve", limits, store, err))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Identity of the output for `path` in the transpile cache. V2 loaders are
+    /// given the path, so their output may differ between files with the same source
+    fn cache_key(&self, path: &str) -> String {
+        match self.exports {
+            Exports::V1 { .. } => self.id.clone(),
+            Exports::V2 { .. } => format!("{}#{}", self.id, path),
+        }
+    }
+
+    /// Transform `source` going through the transpile cache. Declined files
+    /// are not cached, so the loader is asked again next time
+    fn load(
         &mut self,
-        ctx: rquickjs::Ctx<'js>,
         path: &str,
-    ) -> rquickjs::Result<rquickjs::Module<'js, rquickjs::Loaded<()>>> {
-        let source = std::fs::read_to_string(path)?;
-        let output = self
-            .exports
-            .transform(&mut self.store, &source)
-            .map_err(throw!())?;
+        source: &str,
+        cache: Option<&TranspileCache>,
+    ) -> rquickjs::Result<Option<Transformed>> {
+        let key = self.cache_key(path);
 
-        let output = match output {
-            loader::Compilation::Success(ret) => ret,
-            loader::Compilation::Failure(err) => {
-                return Err(rquickjs::Error::new_loading_message(path, err))
-            }
-        };
+        if let Some(cached) = cache.and_then(|m| m.get(&key, source)) {
+            return Ok(Some(Transformed {
+                code: cached.code,
+                map: cached.map,
+            }));
+        }
 
-        Ok(JsModule::new(ctx, path, output)?.into_loaded())
+        let transformed = self.transform(path, source)?;
+
+        if let (Some(cache), Some(transformed)) = (cache, &transformed) {
+            cache.set(&key, source, &transformed.code, transformed.map.as_deref());
+        }
+
+        Ok(transformed)
+    }
+}
+
+fn severity(severity: loader_v2::Severity) -> &'static str {
+    match severity {
+        loader_v2::Severity::Error => "error",
+        loader_v2::Severity::Warning => "warning",
+        loader_v2::Severity::Info => "info",
+    }
+}
+
+fn format_diagnostics(diagnostics: &[loader_v2::Diagnostic]) -> String {
+    let mut out = String::new();
+    for diagnostic in diagnostics {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        write!(
+            out,
+            "{}:{}:{}: {}: {}",
+            diagnostic.file,
+            diagnostic.line,
+            diagnostic.column,
+            severity(diagnostic.severity),
+            diagnostic.message
+        )
+        .ok();
+    }
+    out
+}
+
+fn report(diagnostics: &[loader_v2::Diagnostic]) {
+    for diagnostic in diagnostics {
+        let message = format_diagnostics(std::slice::from_ref(diagnostic));
+        match diagnostic.severity {
+            loader_v2::Severity::Error => log::error!("{}", message),
+            loader_v2::Severity::Warning => log::warn!("{}", message),
+            loader_v2::Severity::Info => log::info!("{}", message),
+        }
     }
 }
 
@@ -46,49 +222,248 @@ impl WasmLoader {
 //     Ok(config)
 // }
 
-fn default_wasi() -> wasmtime_wasi::WasiCtx {
-    wasmtime_wasi::sync::WasiCtxBuilder::new()
+/// Restrictions placed on wasm loaders
+#[derive(Clone, Debug, Default)]
+pub struct Sandbox {
+    /// Fuel available to a single call into a loader
+    pub fuel: Option<u64>,
+    /// Wall clock time a single call into a loader may take
+    pub timeout: Option<Duration>,
+    /// Maximum size of a loader's linear memory in bytes
+    pub memory: Option<usize>,
+    /// Directories loaders may read from. They are mounted read-only at the
+    /// same path as on the host, so paths handed to loaders resolve as is
+    pub preopens: Vec<PathBuf>,
+}
+
+/// Resolution of the epoch used to enforce `Sandbox::timeout`
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Per call limits derived from a `Sandbox`
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Limits {
+    fuel: Option<u64>,
+    ticks: Option<u64>,
+    memory: Option<usize>,
+}
+
+impl Limits {
+    pub(crate) fn new(sandbox: &Sandbox) -> Limits {
+        Limits {
+            fuel: sandbox.fuel,
+            ticks: sandbox.timeout.map(|timeout| {
+                ((timeout.as_millis() / EPOCH_TICK.as_millis()) as u64).max(1)
+            }),
+            memory: sandbox.memory,
+        }
+    }
+
+    /// Refill fuel and reset the deadline before calling into wasm
+    pub(crate) fn arm<I, E>(&self, store: &mut Store<Context<I, E>>) -> anyhow::Result<()> {
+        store.data_mut().limits.denied = false;
+
+        if let Some(fuel) = self.fuel {
+            let remaining = store.consume_fuel(0)?;
+            if remaining < fuel {
+                store.add_fuel(fuel - remaining)?;
+            }
+        }
+
+        if let Some(ticks) = self.ticks {
+            store.set_epoch_deadline(ticks);
+        }
+
+        Ok(())
+    }
+}
+
+/// Advances the epoch of an engine until dropped
+struct Ticker {
+    stop: Arc<AtomicBool>,
+}
+
+impl Ticker {
+    fn start(engine: Engine) -> Ticker {
+        let stop = Arc::new(AtomicBool::new(false));
+
+        std::thread::spawn({
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::Relaxed) {
+                    std::thread::sleep(EPOCH_TICK);
+                    engine.increment_epoch();
+                }
+            }
+        });
+
+        Ticker { stop }
+    }
+}
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn subject(name: &str) -> String {
+    format!("wasm loader '{}'", name)
+}
+
+/// Turn a trap into an error naming the module that raised it and the limit it hit
+pub(crate) fn trap_error<I, E>(
+    subject: &str,
+    func: &str,
+    limits: Limits,
+    store: &mut Store<Context<I, E>>,
+    trap: Trap,
+) -> Error {
+    let reason = match trap.trap_code() {
+        Some(TrapCode::Interrupt) => match limits.fuel {
+            Some(fuel) if store.consume_fuel(0).ok() == Some(0) => {
+                format!("ran out of fuel ({} units)", fuel)
+            }
+            _ => format!(
+                "timed out after {}ms",
+                limits.ticks.unwrap_or_default() * EPOCH_TICK.as_millis() as u64
+            ),
+        },
+        // Allocators abort when memory can not grow, which is all the trap tells
+        Some(TrapCode::UnreachableCodeReached) if store.data().limits.denied => format!(
+            "aborted after exceeding its memory limit of {} bytes",
+            limits.memory.unwrap_or_default()
+        ),
+        _ => match trap.i32_exit_status() {
+            Some(status) => format!("exited with status {}", status),
+            None => format!("trapped: {}", trap),
+        },
+    };
+
+    throw!(format!("{} {} in {}", subject, reason, func))
+}
+
+fn sandboxed_wasi(preopens: &[PathBuf]) -> anyhow::Result<wasmtime_wasi::WasiCtx> {
+    use wasmtime_wasi::sync::{ambient_authority, dir::Dir, Dir as CapDir};
+
+    let mut wasi = wasmtime_wasi::sync::WasiCtxBuilder::new()
         .inherit_stdio()
-        .build()
+        .build();
+
+    let dir_caps = DirCaps::OPEN
+        | DirCaps::READDIR
+        | DirCaps::READLINK
+        | DirCaps::PATH_FILESTAT_GET
+        | DirCaps::FILESTAT_GET;
+
+    let file_caps = FileCaps::READ
+        | FileCaps::SEEK
+        | FileCaps::TELL
+        | FileCaps::ADVISE
+        | FileCaps::FILESTAT_GET
+        | FileCaps::POLL_READWRITE;
+
+    // Descriptors 0-2 are taken by stdio
+    for (fd, path) in (3..).zip(preopens) {
+        let dir = CapDir::open_ambient_dir(path, ambient_authority())
+            .map_err(|err| anyhow::anyhow!("could not preopen {:?}: {}", path, err))?;
+
+        wasi.insert_dir(
+            fd,
+            Box::new(Dir::from_cap_std(dir)),
+            dir_caps,
+            file_caps,
+            path.clone(),
+        );
+    }
+
+    Ok(wasi)
+}
+
+/// `StoreLimits` remembering whether it denied memory to the module since
+/// the limits were last armed
+struct MemoryLimiter {
+    limits: StoreLimits,
+    denied: bool,
+}
+
+impl MemoryLimiter {
+    fn new(limits: Limits) -> MemoryLimiter {
+        let mut builder = StoreLimitsBuilder::new();
+        if let Some(memory) = limits.memory {
+            builder = builder.memory_size(memory);
+        }
+
+        MemoryLimiter {
+            limits: builder.build(),
+            denied: false,
+        }
+    }
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(&mut self, current: usize, desired: usize, maximum: Option<usize>) -> bool {
+        let allowed = self.limits.memory_growing(current, desired, maximum);
+        self.denied |= !allowed;
+        allowed
+    }
+
+    fn table_growing(&mut self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        self.limits.table_growing(current, desired, maximum)
+    }
+
+    fn instances(&self) -> usize {
+        self.limits.instances()
+    }
+
+    fn tables(&self) -> usize {
+        self.limits.tables()
+    }
+
+    fn memories(&self) -> usize {
+        self.limits.memories()
+    }
 }
 
-struct Context<I, E> {
+pub(crate) struct Context<I, E> {
     wasi: wasmtime_wasi::WasiCtx,
+    limits: MemoryLimiter,
     imports: I,
     exports: E,
 }
 
-// fn instantiate<I: Default, E: Default, T>(
-//     wasm: &str,
-//     add_imports: impl FnOnce(&mut Linker<Context<I, E>>) -> Result<()>,
-//     mk_exports: impl FnOnce(
-//         &mut Store<Context<I, E>>,
-//         &Module,
-//         &mut Linker<Context<I, E>>,
-//     ) -> Result<(T, Instance)>,
-// ) -> Result<(T, Store<Context<I, E>>)> {
-//     let engine = Engine::new(&default_config()?)?;
-//     let module = Module::from_file(&engine, wasm)?;
-
-//     let mut linker = Linker::new(&engine);
-//     add_imports(&mut linker)?;
-//     wasmtime_wasi::add_to_linker(&mut linker, |cx| &mut cx.wasi)?;
-
-//     let mut store = Store::new(
-//         &engine,
-//         Context {
-//             wasi: default_wasi(),
-//             imports: I::default(),
-//             exports: E::default(),
-//         },
-//     );
-//     let (exports, _instance) = mk_exports(&mut store, &module, &mut linker)?;
-//     Ok((exports, store))
-// }
+/// Linker with wasi added
+pub(crate) fn linker<I, E>(engine: &Engine) -> anyhow::Result<Linker<Context<I, E>>> {
+    let mut linker = Linker::new(engine);
+    wasmtime_wasi::add_to_linker(&mut linker, |cx: &mut Context<I, E>| &mut cx.wasi)?;
+    Ok(linker)
+}
+
+/// Store confined by `sandbox`
+pub(crate) fn store<I: Default, E: Default>(
+    engine: &Engine,
+    sandbox: &Sandbox,
+) -> anyhow::Result<Store<Context<I, E>>> {
+    let limits = Limits::new(sandbox);
+
+    let mut store = Store::new(
+        engine,
+        Context {
+            wasi: sandboxed_wasi(&sandbox.preopens)?,
+            limits: MemoryLimiter::new(limits),
+            imports: I::default(),
+            exports: E::default(),
+        },
+    );
+    store.limiter(|cx| &mut cx.limits);
+    limits.arm(&mut store)?;
+
+    Ok(store)
+}
 
 fn instantiate<I: Default, E: Default, T>(
     engine: &Engine,
-    wasm: impl AsRef<Path>,
+    module: &Module,
+    sandbox: &Sandbox,
     add_imports: impl FnOnce(&mut Linker<Context<I, E>>) -> anyhow::Result<()>,
     mk_exports: impl FnOnce(
         &mut Store<Context<I, E>>,
@@ -97,39 +472,173 @@ fn instantiate<I: Default, E: Default, T>(
     ) -> anyhow::Result<(T, Instance)>,
 ) -> anyhow::Result<(T, Store<Context<I, E>>)> {
     // let engine = Engine::new(&default_config()?)?;
-    let module = Module::from_file(&engine, wasm)?;
-
-    let mut linker = Linker::new(&engine);
+    let mut linker = linker(engine)?;
     add_imports(&mut linker)?;
-    wasmtime_wasi::add_to_linker(&mut linker, |cx| &mut cx.wasi)?;
 
-    let mut store = Store::new(
-        &engine,
-        Context {
-            wasi: default_wasi(),
-            imports: I::default(),
-            exports: E::default(),
-        },
-    );
+    let mut store = store(engine, sandbox)?;
+
     let (exports, _instance) = mk_exports(&mut store, &module, &mut linker)?;
     Ok((exports, store))
 }
 
-pub fn open<P: AsRef<Path>>(engine: Engine, path: P) -> anyhow::Result<WasmLoader> {
-    use loader::*;
-    let (exports, mut store) = instantiate(
-        &engine,
-        path,
-        |_linker: &mut Linker<Context<(), _>>| Ok(()),
-        |store, module, linker| Loader::instantiate(store, module, linker, |cx| &mut cx.exports),
-    )?;
+/// Whether the module implements `loader-v2.wit`
+fn is_v2(module: &Module) -> bool {
+    module.exports().any(|export| export.name() == "metadata")
+}
 
-    let exts = exports.extension(&mut store)?;
+/// Name of the project configuration file, looked up in the working directory
+pub const PROJECT_CONFIG: &str = "scriptor.toml";
+
+fn merge(base: &mut toml::Value, over: toml::Value) {
+    match (base, over) {
+        (toml::Value::Table(base), toml::Value::Table(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+/// Build the configuration handed to a loader: its sidecar file (`typescript.toml`
+/// next to `typescript.wasm`) with the project configuration merged on top,
+/// encoded as json. Empty when the loader is not configured
+fn loader_config(path: &Path, project: Option<toml::Value>) -> anyhow::Result<String> {
+    let sidecar = path.with_extension("toml");
+
+    let config = match (sidecar.exists(), project) {
+        (false, None) => return Ok(String::new()),
+        (false, Some(project)) => project,
+        (true, project) => {
+            let mut config: toml::Value = toml::from_str(&std::fs::read_to_string(&sidecar)?)?;
+            if let Some(project) = project {
+                merge(&mut config, project);
+            }
+            config
+        }
+    };
+
+    Ok(serde_json::to_string(&config)?)
+}
+
+/// Loader related parts of the project configuration
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ProjectConfig {
+    /// Per loader configuration, keyed by loader name
+    loaders: Option<toml::value::Table>,
+    /// Loaders to chain for an extension, in order. The output of each loader
+    /// is the input of the next
+    pipelines: BTreeMap<String, Vec<String>>,
+}
+
+impl ProjectConfig {
+    fn loader(&self, name: &str) -> Option<toml::Value> {
+        self.loaders.as_ref()?.get(name).cloned()
+    }
+
+    /// Loaders with a higher priority are tried first. Defaults to 0
+    fn priority(&self, name: &str) -> i64 {
+        self.loaders
+            .as_ref()
+            .and_then(|loaders| loaders.get(name)?.get("priority")?.as_integer())
+            .unwrap_or_default()
+    }
+}
+
+/// Read the project configuration in `dir`
+async fn project_config(dir: &Path) -> anyhow::Result<ProjectConfig> {
+    let path = dir.join(PROJECT_CONFIG);
+    if !path.exists() {
+        return Ok(ProjectConfig::default());
+    }
+
+    toml::from_str(&tokio::fs::read_to_string(&path).await?)
+        .map_err(|err| anyhow::anyhow!("{:?}: {}", path, err))
+}
+
+pub fn open<P: AsRef<Path>>(
+    engine: Engine,
+    path: P,
+    project: Option<toml::Value>,
+    sandbox: &Sandbox,
+) -> anyhow::Result<WasmLoader> {
+    let bytes = std::fs::read(path.as_ref())?;
+
+    let config = loader_config(path.as_ref(), project)?;
+
+    let name = path
+        .as_ref()
+        .file_stem()
+        .map(|m| m.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    // Identifies the loader in the transpile cache, so updating the wasm file
+    // or its configuration invalidates everything it produced
+    let id = format!("{}@{}", name, hash(&[&bytes, config.as_bytes()]));
+
+    let limits = Limits::new(sandbox);
+
+    let module = Module::new(&engine, &bytes)?;
+
+    let (exports, exts, resolver) = if is_v2(&module) {
+        let (exports, mut store) = instantiate(
+            &engine,
+            &module,
+            sandbox,
+            |_linker: &mut Linker<V2Context>| Ok(()),
+            |store, module, linker| {
+                loader_v2::LoaderV2::instantiate(store, module, linker, |cx| &mut cx.exports)
+            },
+        )?;
+
+        let metadata = exports
+            .metadata(&mut store)
+            .map_err(|err| trap_error(&subject(&name), "metadata", limits, &mut store, err))?;
+        if metadata.version != 2 {
+            anyhow::bail!(
+                "loader {:?} implements unsupported version {}",
+                path.as_ref(),
+                metadata.version
+            );
+        }
+
+        (
+            Exports::V2 { store, exports },
+            metadata.extensions,
+            metadata.resolver,
+        )
+    } else {
+        let (exports, mut store) = instantiate(
+            &engine,
+            &module,
+            sandbox,
+            |_linker: &mut Linker<V1Context>| Ok(()),
+            |store, module, linker| {
+                loader::Loader::instantiate(store, module, linker, |cx| &mut cx.exports)
+            },
+        )?;
+
+        let exts = exports
+            .extension(&mut store)
+            .map_err(|err| trap_error(&subject(&name), "extension", limits, &mut store, err))?;
+
+        (Exports::V1 { store, exports }, exts, false)
+    };
 
     Ok(WasmLoader {
         exports,
-        store,
         exts,
+        resolver,
+        id,
+        name,
+        config,
+        limits,
     })
 }
 
@@ -137,6 +646,9 @@ pub struct WasmConfig<'a> {
     pub loaders: &'a Path,
     pub config: &'a Path,
     pub cache: Option<&'a Path>,
+    /// Directory containing the project configuration
+    pub project: Option<&'a Path>,
+    pub sandbox: Sandbox,
 }
 
 pub async fn ensure_cache_config(root: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
@@ -169,6 +681,78 @@ files-total-size-soft-limit = "1Gi"
     Ok(cache_config_path)
 }
 
+/// Name of the manifest, stored in the cache directory, recording the
+/// extensions of every installed loader
+pub const MANIFEST: &str = "loaders.json";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ManifestEntry {
+    pub(crate) size: u64,
+    pub(crate) modified: u128,
+    pub(crate) extensions: Vec<String>,
+    pub(crate) resolver: bool,
+    /// Version of the loader interface, 0 in manifests written before it was recorded
+    #[serde(default)]
+    pub(crate) version: u32,
+}
+
+pub(crate) type Manifest = BTreeMap<String, ManifestEntry>;
+
+pub(crate) async fn read_manifest(path: &Path) -> Manifest {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+            log::warn!("ignoring invalid loader manifest {:?}: {}", path, err);
+            Manifest::default()
+        }),
+        Err(_) => Manifest::default(),
+    }
+}
+
+/// Size and modification time identifying the version of a loader file
+pub(crate) fn file_stamp(metadata: &std::fs::Metadata) -> (u64, u128) {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+
+    (metadata.len(), modified)
+}
+
+enum LoaderState {
+    Pending(Option<toml::Value>),
+    Ready(WasmLoader),
+}
+
+/// An installed loader, instantiated the first time it is needed
+pub struct LazyLoader {
+    name: String,
+    priority: i64,
+    path: PathBuf,
+    exts: Vec<String>,
+    resolver: bool,
+    state: LoaderState,
+}
+
+impl LazyLoader {
+    fn get(&mut self, engine: &Engine, sandbox: &Sandbox) -> rquickjs::Result<&mut WasmLoader> {
+        if let LoaderState::Pending(project) = &mut self.state {
+            log::debug!("instantiating loader {:?}", self.path);
+            let loader =
+                open(engine.clone(), &self.path, project.take(), sandbox).map_err(|err| {
+                    Error::new_loading_message(&self.path.to_string_lossy(), err.to_string())
+                })?;
+            self.state = LoaderState::Ready(loader);
+        }
+
+        match &mut self.state {
+            LoaderState::Ready(loader) => Ok(loader),
+            LoaderState::Pending(_) => unreachable!(),
+        }
+    }
+}
+
 pub async fn open_path(cfg: WasmConfig<'_>) -> anyhow::Result<WasmLoaders> {
     use futures_lite::StreamExt;
 
@@ -179,11 +763,37 @@ pub async fn open_path(cfg: WasmConfig<'_>) -> anyhow::Result<WasmLoaders> {
         config.cache_config_load(path)?;
     }
 
+    config
+        .consume_fuel(cfg.sandbox.fuel.is_some())
+        .epoch_interruption(cfg.sandbox.timeout.is_some());
+
     let engine = Engine::new(&config)?;
 
+    let ticker = cfg
+        .sandbox
+        .timeout
+        .map(|_| Rc::new(Ticker::start(engine.clone())));
+
+    let sandbox = Arc::new(cfg.sandbox);
+
+    let project = match cfg.project {
+        Some(dir) => project_config(dir).await?,
+        None => ProjectConfig::default(),
+    };
+
+    let manifest_path = cfg.cache.map(|cache| cache.join(MANIFEST));
+    let manifest = match &manifest_path {
+        Some(path) => read_manifest(path).await,
+        None => Manifest::default(),
+    };
+
     let mut stream = tokio::fs::read_dir(cfg.loaders).await?;
 
     let mut loaders = Vec::default();
+    let mut opening = Vec::default();
+    let mut updated = Manifest::default();
+    // Everything the output of the loaders depends on
+    let mut fingerprint = vec![format!("{:?}", project)];
 
     while let Some(next) = stream.next_entry().await? {
         let path = next.path();
@@ -196,33 +806,278 @@ pub async fn open_path(cfg: WasmConfig<'_>) -> anyhow::Result<WasmLoaders> {
             continue;
         }
 
-        let engine = engine.clone();
+        let file_name = next.file_name().to_string_lossy().to_string();
+        let (size, modified) = file_stamp(&next.metadata().await?);
+
+        fingerprint.push(format!("{}:{}:{}", file_name, size, modified));
+        if let Ok(meta) = tokio::fs::metadata(path.with_extension("toml")).await {
+            let (size, modified) = file_stamp(&meta);
+            fingerprint.push(format!("{}.toml:{}:{}", file_name, size, modified));
+        }
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let priority = project.priority(&name);
+        let config = project.loader(&name);
 
-        loaders.push(tokio::task::spawn_blocking(move || open(engine, path)));
+        // Loaders already in the manifest are only instantiated once a matching
+        // module is imported
+        match manifest.get(&file_name) {
+            Some(entry) if entry.size == size && entry.modified == modified => {
+                loaders.push(LazyLoader {
+                    name,
+                    priority,
+                    path,
+                    exts: entry.extensions.clone(),
+                    resolver: entry.resolver,
+                    state: LoaderState::Pending(config),
+                });
+                updated.insert(file_name, entry.clone());
+            }
+            _ => {
+                let engine = engine.clone();
+                let sandbox = sandbox.clone();
+                let task = tokio::task::spawn_blocking({
+                    let path = path.clone();
+                    move || open(engine, path, config, &sandbox)
+                });
+
+                opening.push((loaders.len(), file_name, size, modified, task));
+
+                // Filled in once the loader is instantiated
+                loaders.push(LazyLoader {
+                    name,
+                    priority,
+                    path,
+                    exts: Vec::new(),
+                    resolver: false,
+                    state: LoaderState::Pending(None),
+                });
+            }
+        }
     }
 
-    let loaders = futures_lite::stream::iter(loaders)
-        .then(|m| async move { m.await })
-        .map(|ret| match ret {
-            Ok(Ok(ret)) => Ok(ret),
-            Ok(Err(err)) => Err(err),
-            Err(err) => Err(anyhow::Error::new(err)),
+    let opened = futures_lite::stream::iter(opening)
+        .then(|(index, file_name, size, modified, task)| async move {
+            match task.await {
+                Ok(Ok(loader)) => Ok((index, file_name, size, modified, loader)),
+                Ok(Err(err)) => Err(err),
+                Err(err) => Err(anyhow::Error::new(err)),
+            }
         })
         .try_collect::<_, _, Vec<_>>()
         .await?;
 
-    Ok(WasmLoaders { loaders })
+    for (index, file_name, size, modified, loader) in opened {
+        updated.insert(
+            file_name,
+            ManifestEntry {
+                size,
+                modified,
+                extensions: loader.exts.clone(),
+                resolver: loader.resolver,
+                version: loader.version(),
+            },
+        );
+
+        let lazy = &mut loaders[index];
+        lazy.exts = loader.exts.clone();
+        lazy.resolver = loader.resolver;
+        lazy.state = LoaderState::Ready(loader);
+    }
+
+    if let Some(path) = manifest_path {
+        if updated != manifest {
+            if let Err(err) = tokio::fs::write(&path, serde_json::to_vec_pretty(&updated)?).await {
+                log::warn!("could not write loader manifest {:?}: {}", path, err);
+            }
+        }
+    }
+
+    // Highest priority first, ties are broken by name so the order does not
+    // depend on the directory listing
+    loaders.sort_by(|a, b| {
+        b.priority
+            .cmp(&a.priority)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    for (ext, pipeline) in &project.pipelines {
+        for name in pipeline {
+            if !loaders.iter().any(|loader| &loader.name == name) {
+                anyhow::bail!("pipeline for .{} uses unknown loader {}", ext, name);
+            }
+        }
+    }
+
+    fingerprint.sort();
+    let fingerprint = hash(
+        &fingerprint
+            .iter()
+            .map(|part| part.as_bytes())
+            .collect::<Vec<_>>(),
+    );
+
+    Ok(WasmLoaders {
+        loaders: Rc::new(RefCell::new(loaders)),
+        pipelines: Rc::new(project.pipelines),
+        fingerprint,
+        source_maps: SourceMaps::default(),
+        cache: None,
+        engine,
+        sandbox,
+        _ticker: ticker,
+    })
 }
 
+#[derive(Clone)]
 pub struct WasmLoaders {
-    loaders: Vec<WasmLoader>,
+    loaders: Rc<RefCell<Vec<LazyLoader>>>,
+    pipelines: Rc<BTreeMap<String, Vec<String>>>,
+    fingerprint: String,
+    source_maps: SourceMaps,
+    cache: Option<TranspileCache>,
+    engine: Engine,
+    sandbox: Arc<Sandbox>,
+    _ticker: Option<Rc<Ticker>>,
 }
 
 impl WasmLoaders {
-    pub fn extensions<'a>(&'a self) -> impl Iterator<Item = &'a str> {
+    /// Register the source maps emitted by loaders in `source_maps`
+    pub fn set_source_maps(&mut self, source_maps: SourceMaps) {
+        self.source_maps = source_maps;
+    }
+
+    pub fn set_cache(&mut self, cache: Option<TranspileCache>) {
+        self.cache = cache;
+    }
+
+    /// Hash of the installed loaders, their configuration and the pipelines.
+    /// Changes whenever loaders may transform a file differently
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    pub fn extensions(&self) -> Vec<String> {
         self.loaders
+            .borrow()
             .iter()
-            .flat_map(|m| m.exts.iter().map(|m| m.as_str()))
+            .flat_map(|m| m.exts.iter().cloned())
+            .chain(self.pipelines.keys().cloned())
+            .collect()
+    }
+
+    /// Run `source` through the loaders for `ext`: every loader of the
+    /// extension's pipeline, or else the first loader by priority not
+    /// declining the file
+    fn transform(&self, path: &str, ext: &str, source: String) -> rquickjs::Result<Transformed> {
+        let mut loaders = self.loaders.borrow_mut();
+
+        match self.pipelines.get(ext) {
+            Some(pipeline) => {
+                let mut current = Transformed {
+                    code: source,
+                    map: None,
+                };
+                let mut accepted = false;
+
+                for name in pipeline {
+                    let loader = match loaders.iter_mut().find(|loader| &loader.name == name) {
+                        Some(loader) => loader.get(&self.engine, &self.sandbox)?,
+                        None => return Err(Error::new_loading(path)),
+                    };
+
+                    if let Some(transformed) =
+                        loader.load(path, &current.code, self.cache.as_ref())?
+                    {
+                        let map = transformed
+                            .map
+                            .or_else(|| source_map::inline_map(&transformed.code));
+
+                        // The map of every loader after the first leads back to the
+                        // output of the one before it, so they are chained
+                        current = Transformed {
+                            map: match (accepted, current.map, map) {
+                                (false, _, map) => map,
+                                (true, Some(first), Some(second)) => {
+                                    source_map::compose(&first, &second)
+                                }
+                                _ => None,
+                            },
+                            code: transformed.code,
+                        };
+                        accepted = true;
+                    }
+                }
+
+                if !accepted {
+                    return Err(Error::new_loading_message(path, "declined by every loader"));
+                }
+
+                Ok(current)
+            }
+            None => {
+                for loader in loaders
+                    .iter_mut()
+                    .filter(|loader| loader.exts.iter().any(|e| e == ext))
+                {
+                    let loader = loader.get(&self.engine, &self.sandbox)?;
+                    if let Some(transformed) = loader.load(path, &source, self.cache.as_ref())? {
+                        return Ok(transformed);
+                    }
+                }
+
+                Err(Error::new_loading(path))
+            }
+        }
+    }
+}
+
+impl WasmLoaders {
+    /// Whether a pipeline or loader is configured for the extension of `path`.
+    /// Other files, as well as asset and remote modules, are declined without
+    /// being read so the next loader gets them
+    fn handles(&self, path: &str) -> bool {
+        if !is_file_name(path) {
+            return false;
+        }
+
+        let ext = extension(path);
+        self.pipelines.contains_key(&ext)
+            || self
+                .loaders
+                .borrow()
+                .iter()
+                .any(|loader| loader.exts.contains(&ext))
+    }
+
+    /// Javascript source of the file at `path`, transformed by the loaders
+    /// for its extension. The maps of the loaders in a pipeline are composed,
+    /// and the module gets no map when one of them has none
+    pub(crate) fn transform_file(&self, path: &str) -> rquickjs::Result<String> {
+        if !self.handles(path) {
+            return Err(Error::new_loading(path));
+        }
+
+        let ext = extension(path);
+        let source = std::fs::read_to_string(path)?;
+        let transformed = self.transform(path, &ext, source)?;
+
+        // Rewriting keeps lines in place, so the map of the loader output applies
+        let code = crate::assets::rewrite_import_attributes(&transformed.code).into_owned();
+
+        match &transformed.map {
+            Some(map) => self.source_maps.insert(path, map),
+            // An inline map left in the output of a pipeline only covers its last loader
+            None if !self.pipelines.contains_key(&ext) => {
+                self.source_maps.insert_inline(path, &code)
+            }
+            None => {}
+        }
+
+        Ok(code)
     }
 }
 
@@ -232,24 +1087,79 @@ impl Loader for WasmLoaders {
         ctx: rquickjs::Ctx<'js>,
         p: &str,
     ) -> rquickjs::Result<rquickjs::Module<'js, rquickjs::Loaded<()>>> {
-        let path = Path::new(p);
-        let ext = path
-            .extension()
-            .map(|m| m.to_string_lossy())
-            .unwrap_or_default()
-            .to_string();
+        if !is_file_name(p) {
+            return Err(Error::new_loading(p));
+        }
+
+        if extension(p) == crate::wasm_module::EXTENSION {
+            return crate::wasm_module::load(ctx, &self.engine, &self.sandbox, p);
+        }
+
+        // `transform_file` releases its borrow of the loaders before the
+        // module is compiled, as that may resolve imports through this loader set
+        let code = self.transform_file(p)?;
 
-        for loader in self
-            .loaders
-            .iter_mut()
-            .filter(|loader| loader.exts.contains(&ext))
-        {
-            match loader.load(ctx, p) {
-                Ok(ret) => return Ok(ret),
-                Err(err) => return Err(err),
+        Ok(JsModule::new(ctx, p, code.as_bytes())?.into_loaded())
+    }
+}
+
+/// Whether `name` is a plain path, rather than a prefixed (`bytes:./x.wasm`)
+/// or remote (`https://...`) module name. Drive letters are not prefixes
+fn is_file_name(name: &str) -> bool {
+    match name.split_once(':') {
+        Some((prefix, _)) => prefix.len() == 1 && prefix.chars().all(|c| c.is_ascii_alphabetic()),
+        None => true,
+    }
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .map(|m| m.to_string_lossy())
+        .unwrap_or_default()
+        .to_string()
+}
+
+impl Resolver for WasmLoaders {
+    fn resolve<'js>(
+        &mut self,
+        _ctx: rquickjs::Ctx<'js>,
+        base: &str,
+        name: &str,
+    ) -> rquickjs::Result<String> {
+        // Only bare specifiers are handed to loaders
+        if name.starts_with('.') || name.starts_with('/') {
+            return Err(Error::new_resolving(base, name));
+        }
+
+        for loader in self.loaders.borrow_mut().iter_mut() {
+            if !loader.resolver {
+                continue;
+            }
+
+            if let Some(path) = loader
+                .get(&self.engine, &self.sandbox)?
+                .resolve(base, name)?
+            {
+                return Ok(path);
             }
         }
 
-        Err(rquickjs::Error::new_loading(p))
+        Err(Error::new_resolving(base, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_file_names_from_prefixed_names() {
+        assert!(is_file_name("./module.ts"));
+        assert!(is_file_name("/abs/module.ts"));
+        assert!(is_file_name("C:\\project\\module.ts"));
+        assert!(!is_file_name("text:./template.html"));
+        assert!(!is_file_name("bytes:./module.wasm"));
+        assert!(!is_file_name("https://example.com/module.ts"));
     }
 }
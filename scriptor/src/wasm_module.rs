@@ -0,0 +1,762 @@
+//! Plain wasm modules imported from javascript.
+//!
+//! `import { add } from "./math.wasm"` instantiates the module in the loader
+//! sandbox and exposes its exports. Functions take and return numbers, unless
+//! a `math.wit` file next to the module describes them, in which case strings
+//! and lists are passed using the canonical abi of wit-bindgen.
+
+use std::{cell::RefCell, path::Path, rc::Rc};
+
+use rquickjs::{
+    Ctx, FromJs, Func, IntoJs, Module as JsModule, Object, Rest, Result, TypedArray, Value,
+};
+use wasmtime::{
+    Engine, Extern, Func as WasmFunc, Memory, Module, Mutability, Store, Trap, Val, ValType,
+};
+use wit_parser::{Interface, Type, TypeDefKind};
+
+use crate::wasm_loader::{linker, store, trap_error, Context, Limits, Sandbox};
+
+pub const EXTENSION: &str = "wasm";
+
+type ModuleStore = Rc<RefCell<Store<Context<(), ()>>>>;
+
+pub fn load<'js>(
+    ctx: Ctx<'js>,
+    engine: &Engine,
+    sandbox: &Sandbox,
+    path: &str,
+) -> Result<JsModule<'js, rquickjs::Loaded<()>>> {
+    let instance = Instance::new(engine, sandbox, Path::new(path)).map_err(throw!())?;
+
+    let exports = Object::new(ctx)?;
+    let mut names = Vec::new();
+
+    for (name, export) in instance.exports {
+        match export {
+            Export::Func(func) => {
+                let store = instance.store.clone();
+                exports.set(
+                    name.as_str(),
+                    Func::new(name.as_str(), move |args: Rest<Arg>| {
+                        func.call(&store, args.0)
+                    }),
+                )?
+            }
+            Export::Memory(memory) => {
+                exports.set(name.as_str(), memory_object(ctx, &instance.store, memory)?)?
+            }
+            Export::Global(value) => exports.set(name.as_str(), value)?,
+        }
+        names.push(name);
+    }
+
+    // The generated module picks the exports up from a global that is removed
+    // again as soon as the module is evaluated
+    let key = format!("__wasm:{}", path);
+    ctx.globals().set(key.as_str(), exports)?;
+
+    let key = serde_json::to_string(&key).map_err(throw!())?;
+    let mut source = format!(
+        "const exports = globalThis[{key}];\ndelete globalThis[{key}];\nexport default exports;\n",
+        key = key
+    );
+
+    for name in names.iter().filter(|name| is_identifier(name)) {
+        source.push_str(&format!("export const {0} = exports.{0};\n", name));
+    }
+
+    Ok(JsModule::new(ctx, path, source)?.into_loaded())
+}
+
+struct Instance {
+    store: ModuleStore,
+    exports: Vec<(String, Export)>,
+}
+
+enum Export {
+    Func(Function),
+    Memory(Memory),
+    Global(f64),
+}
+
+impl Instance {
+    fn new(engine: &Engine, sandbox: &Sandbox, path: &Path) -> anyhow::Result<Instance> {
+        let module = Module::from_file(engine, path)?;
+        let name = path
+            .file_name()
+            .map(|m| m.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let wit = path.with_extension("wit");
+        let interface = if wit.exists() {
+            Some(Interface::parse_file(&wit)?)
+        } else {
+            None
+        };
+
+        let limits = Limits::new(sandbox);
+        let mut store = store::<(), ()>(engine, sandbox)?;
+        let instance = linker(engine)?.instantiate(&mut store, &module)?;
+
+        // Reactors export `_initialize`, which has to run before anything else
+        if let Some(init) = instance.get_func(&mut store, "_initialize") {
+            init.call(&mut store, &[], &mut [])?;
+        }
+
+        let realloc = instance.get_func(&mut store, "canonical_abi_realloc");
+        let free = instance.get_func(&mut store, "canonical_abi_free");
+        let memory = instance.get_memory(&mut store, "memory");
+
+        let mut exports = Vec::new();
+
+        if let Some(interface) = &interface {
+            for func in &interface.functions {
+                let export = instance.get_func(&mut store, &func.name).ok_or_else(|| {
+                    anyhow::anyhow!("{:?} does not export {} from {:?}", path, func.name, wit)
+                })?;
+
+                let params = func
+                    .params
+                    .iter()
+                    .map(|(_, ty)| AbiType::new(interface, ty))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+
+                let result = AbiType::new(interface, &func.result)?;
+
+                if (params.iter().any(AbiType::is_indirect) || result.is_indirect())
+                    && (memory.is_none() || realloc.is_none())
+                {
+                    anyhow::bail!(
+                        "{:?} must export memory and canonical_abi_realloc to pass strings and lists",
+                        path
+                    );
+                }
+
+                exports.push((
+                    camel_case(&func.name),
+                    Export::Func(Function {
+                        module: name.clone(),
+                        name: func.name.clone(),
+                        func: export,
+                        signature: Some(Signature { params, result }),
+                        memory,
+                        realloc,
+                        free,
+                        limits,
+                    }),
+                ));
+            }
+        }
+
+        for export in module.exports() {
+            let export_name = export.name();
+            if is_internal(export_name)
+                || interface
+                    .as_ref()
+                    .map(|i| i.functions.iter().any(|f| f.name == export_name))
+                    .unwrap_or_default()
+            {
+                continue;
+            }
+
+            let value = match instance.get_export(&mut store, export_name) {
+                Some(Extern::Func(func)) => Export::Func(Function {
+                    module: name.clone(),
+                    name: export_name.to_string(),
+                    func,
+                    signature: None,
+                    memory,
+                    realloc,
+                    free,
+                    limits,
+                }),
+                Some(Extern::Memory(memory)) => Export::Memory(memory),
+                Some(Extern::Global(global))
+                    if global.ty(&store).mutability() == Mutability::Const =>
+                {
+                    match val_to_number(global.get(&mut store)) {
+                        Some(value) => Export::Global(value),
+                        None => continue,
+                    }
+                }
+                _ => continue,
+            };
+
+            exports.push((camel_case(export_name), value));
+        }
+
+        Ok(Instance {
+            store: Rc::new(RefCell::new(store)),
+            exports,
+        })
+    }
+}
+
+/// Exports used to implement the canonical abi rather than meant for callers
+fn is_internal(name: &str) -> bool {
+    name.starts_with("canonical_abi_") || name == "_initialize" || name == "_start"
+}
+
+/// Subset of wit types that can cross between javascript and wasm
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Primitive {
+    Bool,
+    U8,
+    S8,
+    U16,
+    S16,
+    U32,
+    S32,
+    U64,
+    S64,
+    F32,
+    F64,
+    Char,
+}
+
+impl Primitive {
+    fn size(self) -> usize {
+        match self {
+            Primitive::Bool | Primitive::U8 | Primitive::S8 => 1,
+            Primitive::U16 | Primitive::S16 => 2,
+            Primitive::U32 | Primitive::S32 | Primitive::F32 | Primitive::Char => 4,
+            Primitive::U64 | Primitive::S64 | Primitive::F64 => 8,
+        }
+    }
+
+    fn to_val(self, value: f64) -> Val {
+        match self {
+            Primitive::U64 | Primitive::S64 => Val::I64(value as i64),
+            Primitive::F32 => Val::F32((value as f32).to_bits()),
+            Primitive::F64 => Val::F64(value.to_bits()),
+            Primitive::U32 => Val::I32(value as u32 as i32),
+            _ => Val::I32(value as i32),
+        }
+    }
+
+    fn from_val(self, val: &Val) -> Arg {
+        match (self, val) {
+            (Primitive::Bool, Val::I32(v)) => Arg::Bool(*v != 0),
+            (Primitive::Char, Val::I32(v)) => Arg::String(
+                char::from_u32(*v as u32)
+                    .map(String::from)
+                    .unwrap_or_default(),
+            ),
+            (Primitive::U8, Val::I32(v)) => Arg::Number(*v as u8 as f64),
+            (Primitive::U16, Val::I32(v)) => Arg::Number(*v as u16 as f64),
+            (Primitive::U32, Val::I32(v)) => Arg::Number(*v as u32 as f64),
+            (Primitive::U64, Val::I64(v)) => Arg::Number(*v as u64 as f64),
+            (_, val) => val_to_number(val.clone())
+                .map(Arg::Number)
+                .unwrap_or(Arg::Undefined),
+        }
+    }
+
+    fn write(self, value: f64, out: &mut Vec<u8>) {
+        match self {
+            Primitive::Bool | Primitive::U8 | Primitive::S8 => out.push(value as i64 as u8),
+            Primitive::U16 | Primitive::S16 => {
+                out.extend_from_slice(&(value as i64 as u16).to_le_bytes())
+            }
+            Primitive::U32 | Primitive::S32 | Primitive::Char => {
+                out.extend_from_slice(&(value as i64 as u32).to_le_bytes())
+            }
+            Primitive::U64 | Primitive::S64 => out.extend_from_slice(&(value as i64).to_le_bytes()),
+            Primitive::F32 => out.extend_from_slice(&(value as f32).to_le_bytes()),
+            Primitive::F64 => out.extend_from_slice(&value.to_le_bytes()),
+        }
+    }
+
+    fn read(self, bytes: &[u8]) -> f64 {
+        let mut buf = [0u8; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        match self {
+            Primitive::Bool | Primitive::U8 => bytes[0] as f64,
+            Primitive::S8 => bytes[0] as i8 as f64,
+            Primitive::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Primitive::S16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Primitive::U32 | Primitive::Char => {
+                u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64
+            }
+            Primitive::S32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Primitive::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Primitive::U64 => u64::from_le_bytes(buf) as f64,
+            Primitive::S64 => i64::from_le_bytes(buf) as f64,
+            Primitive::F64 => f64::from_le_bytes(buf),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum AbiType {
+    Unit,
+    Primitive(Primitive),
+    String,
+    List(Primitive),
+}
+
+impl AbiType {
+    fn new(interface: &Interface, ty: &Type) -> anyhow::Result<AbiType> {
+        let ty = match ty {
+            Type::Unit => AbiType::Unit,
+            Type::Bool => AbiType::Primitive(Primitive::Bool),
+            Type::U8 => AbiType::Primitive(Primitive::U8),
+            Type::S8 => AbiType::Primitive(Primitive::S8),
+            Type::U16 => AbiType::Primitive(Primitive::U16),
+            Type::S16 => AbiType::Primitive(Primitive::S16),
+            Type::U32 => AbiType::Primitive(Primitive::U32),
+            Type::S32 => AbiType::Primitive(Primitive::S32),
+            Type::U64 => AbiType::Primitive(Primitive::U64),
+            Type::S64 => AbiType::Primitive(Primitive::S64),
+            Type::Float32 => AbiType::Primitive(Primitive::F32),
+            Type::Float64 => AbiType::Primitive(Primitive::F64),
+            Type::Char => AbiType::Primitive(Primitive::Char),
+            Type::String => AbiType::String,
+            Type::Id(id) => match &interface.types[*id].kind {
+                TypeDefKind::Type(ty) => AbiType::new(interface, ty)?,
+                TypeDefKind::List(ty) => match AbiType::new(interface, ty)? {
+                    AbiType::Primitive(primitive) => AbiType::List(primitive),
+                    _ => anyhow::bail!("only lists of numbers are supported"),
+                },
+                kind => anyhow::bail!("unsupported wit type: {:?}", kind),
+            },
+            ty => anyhow::bail!("unsupported wit type: {:?}", ty),
+        };
+
+        Ok(ty)
+    }
+
+    /// Passed as a pointer and a length
+    fn is_indirect(&self) -> bool {
+        matches!(self, AbiType::String | AbiType::List(_))
+    }
+}
+
+struct Signature {
+    params: Vec<AbiType>,
+    result: AbiType,
+}
+
+/// Values passed between javascript and wasm functions
+#[derive(Debug)]
+enum Arg {
+    Undefined,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<f64>),
+    Bytes(Vec<u8>),
+}
+
+impl Arg {
+    fn number(&self) -> Result<f64> {
+        match self {
+            Arg::Bool(b) => Ok(*b as u8 as f64),
+            Arg::Number(n) => Ok(*n),
+            Arg::String(s) if s.chars().count() == 1 => Ok(s.chars().next().unwrap() as u32 as f64),
+            arg => Err(throw!(format!("expected a number, got {:?}", arg))),
+        }
+    }
+}
+
+impl<'js> FromJs<'js> for Arg {
+    fn from_js(ctx: Ctx<'js>, value: Value<'js>) -> Result<Self> {
+        if let Some(b) = value.as_bool() {
+            Ok(Arg::Bool(b))
+        } else if let Some(n) = value.as_number() {
+            Ok(Arg::Number(n))
+        } else if value.is_string() {
+            String::from_js(ctx, value).map(Arg::String)
+        } else if let Ok(bytes) = TypedArray::<u8>::from_js(ctx, value.clone()) {
+            Ok(Arg::Bytes(bytes.as_ref().to_vec()))
+        } else if value.is_array() {
+            Vec::<f64>::from_js(ctx, value).map(Arg::List)
+        } else if value.is_undefined() || value.is_null() {
+            Ok(Arg::Undefined)
+        } else {
+            Err(throw!("unsupported argument for wasm function"))
+        }
+    }
+}
+
+impl<'js> IntoJs<'js> for Arg {
+    fn into_js(self, ctx: Ctx<'js>) -> Result<Value<'js>> {
+        match self {
+            Arg::Undefined => ().into_js(ctx),
+            Arg::Bool(b) => b.into_js(ctx),
+            Arg::Number(n) => n.into_js(ctx),
+            Arg::String(s) => s.into_js(ctx),
+            Arg::List(list) => list.into_js(ctx),
+            Arg::Bytes(bytes) => TypedArray::new_copy(ctx, &bytes).map(|m| m.into_value()),
+        }
+    }
+}
+
+struct Function {
+    module: String,
+    name: String,
+    func: WasmFunc,
+    signature: Option<Signature>,
+    memory: Option<Memory>,
+    realloc: Option<WasmFunc>,
+    free: Option<WasmFunc>,
+    limits: Limits,
+}
+
+impl Function {
+    fn call(&self, store: &ModuleStore, args: Vec<Arg>) -> Result<Arg> {
+        let mut store = store.borrow_mut();
+        self.limits.arm(&mut *store).map_err(throw!())?;
+
+        match &self.signature {
+            Some(signature) => self.call_wit(&mut store, signature, args),
+            None => self.call_core(&mut store, args),
+        }
+    }
+
+    fn call_core(&self, store: &mut Store<Context<(), ()>>, args: Vec<Arg>) -> Result<Arg> {
+        let ty = self.func.ty(&*store);
+
+        let mut params = Vec::new();
+        for (ty, arg) in ty
+            .params()
+            .zip(args.iter().map(Some).chain(std::iter::repeat(None)))
+        {
+            let value = match arg {
+                Some(arg) => arg.number()?,
+                None => 0.0,
+            };
+            params.push(match ty {
+                ValType::I32 => Val::I32(value as i64 as i32),
+                ValType::I64 => Val::I64(value as i64),
+                ValType::F32 => Val::F32((value as f32).to_bits()),
+                ValType::F64 => Val::F64(value.to_bits()),
+                ty => return Err(throw!(format!("unsupported parameter type {:?}", ty))),
+            });
+        }
+
+        let mut results = ty.results().map(|_| Val::I32(0)).collect::<Vec<_>>();
+        self.invoke(store, &params, &mut results)?;
+
+        let mut numbers = results
+            .into_iter()
+            .filter_map(val_to_number)
+            .collect::<Vec<_>>();
+        Ok(match numbers.len() {
+            0 => Arg::Undefined,
+            1 => Arg::Number(numbers.remove(0)),
+            _ => Arg::List(numbers),
+        })
+    }
+
+    fn call_wit(
+        &self,
+        store: &mut Store<Context<(), ()>>,
+        signature: &Signature,
+        args: Vec<Arg>,
+    ) -> Result<Arg> {
+        let mut params = Vec::new();
+        let mut args = args.into_iter();
+
+        for ty in &signature.params {
+            let arg = args.next().unwrap_or(Arg::Undefined);
+            match ty {
+                AbiType::Unit => {}
+                AbiType::Primitive(primitive) => params.push(primitive.to_val(arg.number()?)),
+                AbiType::String => {
+                    let string = match arg {
+                        Arg::String(s) => s,
+                        arg => return Err(throw!(format!("expected a string, got {:?}", arg))),
+                    };
+                    let ptr = self.copy_in(store, string.as_bytes(), 1)?;
+                    params.push(Val::I32(ptr));
+                    params.push(Val::I32(string.len() as i32));
+                }
+                AbiType::List(primitive) => {
+                    let (bytes, len) = match arg {
+                        Arg::Bytes(bytes) if primitive.size() == 1 => {
+                            let len = bytes.len();
+                            (bytes, len)
+                        }
+                        Arg::List(list) => {
+                            let mut bytes = Vec::with_capacity(list.len() * primitive.size());
+                            for value in &list {
+                                primitive.write(*value, &mut bytes);
+                            }
+                            (bytes, list.len())
+                        }
+                        arg => return Err(throw!(format!("expected a list, got {:?}", arg))),
+                    };
+                    let ptr = self.copy_in(store, &bytes, primitive.size() as i32)?;
+                    params.push(Val::I32(ptr));
+                    params.push(Val::I32(len as i32));
+                }
+            }
+        }
+
+        let mut results = match signature.result {
+            AbiType::Unit => Vec::new(),
+            AbiType::Primitive(Primitive::U64 | Primitive::S64) => vec![Val::I64(0)],
+            AbiType::Primitive(Primitive::F32) => vec![Val::F32(0)],
+            AbiType::Primitive(Primitive::F64) => vec![Val::F64(0)],
+            _ => vec![Val::I32(0)],
+        };
+
+        self.invoke(store, &params, &mut results)?;
+
+        match signature.result {
+            AbiType::Unit => Ok(Arg::Undefined),
+            AbiType::Primitive(primitive) => Ok(primitive.from_val(&results[0])),
+            AbiType::String => {
+                let bytes = self.copy_out(store, &results[0], 1, 1)?;
+                Ok(Arg::String(String::from_utf8(bytes)?))
+            }
+            AbiType::List(primitive) => {
+                let size = primitive.size();
+                let bytes = self.copy_out(store, &results[0], size, size)?;
+                if primitive == Primitive::U8 {
+                    Ok(Arg::Bytes(bytes))
+                } else {
+                    Ok(Arg::List(
+                        bytes
+                            .chunks(size)
+                            .map(|chunk| primitive.read(chunk))
+                            .collect(),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn invoke(
+        &self,
+        store: &mut Store<Context<(), ()>>,
+        params: &[Val],
+        results: &mut [Val],
+    ) -> Result<()> {
+        self.func
+            .call(&mut *store, params, results)
+            .map_err(|err| match err.downcast::<Trap>() {
+                Ok(trap) => trap_error(
+                    &format!("wasm module '{}'", self.module),
+                    &self.name,
+                    self.limits,
+                    store,
+                    trap,
+                ),
+                Err(err) => throw!(err),
+            })
+    }
+
+    /// Allocate memory in the instance and copy `bytes` into it. The callee owns the allocation
+    fn copy_in(&self, store: &mut Store<Context<(), ()>>, bytes: &[u8], align: i32) -> Result<i32> {
+        let (memory, realloc) = match (self.memory, self.realloc) {
+            (Some(memory), Some(realloc)) => (memory, realloc),
+            _ => return Err(throw!("module cannot receive strings or lists")),
+        };
+
+        let mut ptr = [Val::I32(0)];
+        realloc
+            .call(
+                &mut *store,
+                &[
+                    Val::I32(0),
+                    Val::I32(0),
+                    Val::I32(align),
+                    Val::I32(bytes.len() as i32),
+                ],
+                &mut ptr,
+            )
+            .map_err(throw!())?;
+
+        let ptr = ptr[0].unwrap_i32();
+        memory
+            .write(&mut *store, ptr as u32 as usize, bytes)
+            .map_err(throw!())?;
+
+        Ok(ptr)
+    }
+
+    /// Read the `(ptr, len)` pair stored at the return area `retptr` and release the buffer
+    fn copy_out(
+        &self,
+        store: &mut Store<Context<(), ()>>,
+        retptr: &Val,
+        size: usize,
+        align: usize,
+    ) -> Result<Vec<u8>> {
+        let memory = match self.memory {
+            Some(memory) => memory,
+            None => return Err(throw!("module cannot return strings or lists")),
+        };
+
+        let mut area = [0u8; 8];
+        memory
+            .read(&*store, retptr.unwrap_i32() as u32 as usize, &mut area)
+            .map_err(throw!())?;
+
+        let ptr = i32::from_le_bytes([area[0], area[1], area[2], area[3]]);
+        let count = u32::from_le_bytes([area[4], area[5], area[6], area[7]]) as usize;
+        let len = count
+            .checked_mul(size)
+            .ok_or_else(|| throw!(format!("module returned a list of {} elements", count)))?;
+
+        let bytes = read_memory(store, memory, ptr as u32 as usize, len)?;
+
+        if let Some(free) = self.free {
+            free.call(
+                &mut *store,
+                &[Val::I32(ptr), Val::I32(len as i32), Val::I32(align as i32)],
+                &mut [],
+            )
+            .map_err(throw!())?;
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// Copy `len` bytes at `offset` out of `memory`, checking they are in bounds
+/// before allocating room for them
+fn read_memory(
+    store: &Store<Context<(), ()>>,
+    memory: Memory,
+    offset: usize,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let end = offset.checked_add(len);
+    if !matches!(end, Some(end) if end <= memory.data_size(store)) {
+        return Err(throw!(format!(
+            "{} bytes at {} are out of the bounds of the memory",
+            len, offset
+        )));
+    }
+
+    let mut bytes = vec![0u8; len];
+    memory.read(store, offset, &mut bytes).map_err(throw!())?;
+    Ok(bytes)
+}
+
+/// Exported memories are exposed as objects copying in and out of the instance
+fn memory_object<'js>(ctx: Ctx<'js>, store: &ModuleStore, memory: Memory) -> Result<Object<'js>> {
+    let object = Object::new(ctx)?;
+
+    object.set(
+        "size",
+        Func::new("size", {
+            let store = store.clone();
+            move || -> Result<f64> { Ok(memory.data_size(&*store.borrow()) as f64) }
+        }),
+    )?;
+
+    object.set(
+        "read",
+        Func::new("read", {
+            let store = store.clone();
+            move |offset: u32, len: u32| -> Result<Arg> {
+                let bytes = read_memory(&store.borrow(), memory, offset as usize, len as usize)?;
+                Ok(Arg::Bytes(bytes))
+            }
+        }),
+    )?;
+
+    object.set(
+        "write",
+        Func::new("write", {
+            let store = store.clone();
+            move |offset: u32, bytes: Arg| -> Result<()> {
+                let bytes = match bytes {
+                    Arg::Bytes(bytes) => bytes,
+                    Arg::String(string) => string.into_bytes(),
+                    arg => return Err(throw!(format!("expected bytes, got {:?}", arg))),
+                };
+                memory
+                    .write(&mut *store.borrow_mut(), offset as usize, &bytes)
+                    .map_err(throw!())
+            }
+        }),
+    )?;
+
+    Ok(object)
+}
+
+fn val_to_number(val: Val) -> Option<f64> {
+    match val {
+        Val::I32(v) => Some(v as f64),
+        Val::I64(v) => Some(v as f64),
+        Val::F32(v) => Some(f32::from_bits(v) as f64),
+        Val::F64(v) => Some(f64::from_bits(v)),
+        _ => None,
+    }
+}
+
+/// `add-one` -> `addOne`
+fn camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '-' {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn is_identifier(name: &str) -> bool {
+    const RESERVED: &[&str] = &[
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "debugger",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "enum",
+        "export",
+        "exports",
+        "extends",
+        "false",
+        "finally",
+        "for",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "let",
+        "new",
+        "null",
+        "return",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "typeof",
+        "var",
+        "void",
+        "while",
+        "with",
+        "yield",
+    ];
+
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$' => {}
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$') && !RESERVED.contains(&name)
+}
@@ -0,0 +1,236 @@
+//! Rerunning a script whenever one of the files it loaded changes.
+//!
+//! Every vm records the files behind the modules it loads. `watch_main` polls
+//! their modification times and, once changes have settled, drops the running
+//! vm and starts the script again in a fresh one.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
+
+use rquickjs::{Ctx, IntoJs, Loaded, Loader, Module, Result};
+
+use crate::{utils::normalize_path, VmBuilder};
+
+/// Files loaded by a vm
+#[derive(Clone, Debug, Default)]
+pub struct LoadedFiles(Rc<RefCell<BTreeSet<PathBuf>>>);
+
+impl LoadedFiles {
+    pub fn insert(&self, path: impl Into<PathBuf>) {
+        self.0.borrow_mut().insert(path.into());
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.0.borrow().iter().cloned().collect()
+    }
+}
+
+/// Records the file behind every module `inner` loads
+pub(crate) struct TrackingLoader<L> {
+    inner: L,
+    files: LoadedFiles,
+    cwd: PathBuf,
+}
+
+impl<L> TrackingLoader<L> {
+    pub fn new(inner: L, files: LoadedFiles, cwd: impl Into<PathBuf>) -> TrackingLoader<L> {
+        TrackingLoader {
+            inner,
+            files,
+            cwd: cwd.into(),
+        }
+    }
+
+    fn file(&self, name: &str) -> Option<PathBuf> {
+        // Assets are loaded as `text:<path>` and the like
+        let name = name
+            .split_once(':')
+            .filter(|(prefix, _)| matches!(*prefix, "json" | "text" | "bytes"))
+            .map(|(_, path)| path)
+            .unwrap_or(name);
+
+        let path = self.cwd.join(name);
+        if path.is_file() {
+            Some(normalize_path(&path))
+        } else {
+            None
+        }
+    }
+}
+
+impl<L, T> Loader<T> for TrackingLoader<L>
+where
+    L: Loader<T>,
+{
+    fn load<'js>(&mut self, ctx: Ctx<'js>, name: &str) -> Result<Module<'js, Loaded<T>>> {
+        let module = self.inner.load(ctx, name)?;
+        if let Some(path) = self.file(name) {
+            self.files.insert(path);
+        }
+        Ok(module)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+    /// How often files are checked for changes
+    pub interval: Duration,
+    /// How long files must stay unchanged before the script is rerun
+    pub debounce: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            interval: Duration::from_millis(250),
+            debounce: Duration::from_millis(100),
+        }
+    }
+}
+
+struct Watcher {
+    files: LoadedFiles,
+    seen: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+impl Watcher {
+    fn new(files: LoadedFiles) -> Watcher {
+        let mut watcher = Watcher {
+            files,
+            seen: BTreeMap::default(),
+        };
+        watcher.poll();
+        watcher
+    }
+
+    /// Whether a file changed since the last poll. Files not seen before are
+    /// recorded, not reported
+    fn poll(&mut self) -> bool {
+        let mut changed = false;
+
+        for path in self.files.paths() {
+            let modified = std::fs::metadata(&path)
+                .and_then(|meta| meta.modified())
+                .ok();
+
+            match self.seen.insert(path, modified) {
+                Some(previous) if previous != modified => changed = true,
+                _ => {}
+            }
+        }
+
+        changed
+    }
+
+    async fn changed(&mut self, options: &WatchOptions) {
+        while !self.poll() {
+            tokio::time::sleep(options.interval).await;
+        }
+
+        // Editors tend to write files in several steps
+        loop {
+            tokio::time::sleep(options.debounce).await;
+            if !self.poll() {
+                break;
+            }
+        }
+    }
+}
+
+/// Run the main function of the module at `path` in a vm created with `build`,
+/// and again in a fresh one whenever a file loaded by the previous run changes.
+/// A run still in progress is torn down, its pending tasks included, first.
+/// `path` is relative to the working directory of the vm. `on_run` gets the
+/// result of every run that finished.
+///
+/// Only returns when a vm can not be built
+pub async fn watch_main<B, A, R>(
+    mut build: B,
+    path: impl AsRef<Path>,
+    args: A,
+    options: &WatchOptions,
+    mut on_run: R,
+) -> Result<()>
+where
+    B: FnMut() -> VmBuilder,
+    for<'js> A: IntoJs<'js> + Clone,
+    R: FnMut(Result<i32>),
+{
+    let path = path.as_ref();
+
+    loop {
+        let mut vm = build().build().await?;
+        let main = normalize_path(&vm.cwd().join(path));
+
+        // The main module is watched even if it fails to load
+        let files = vm.loaded_files();
+        files.insert(&main);
+
+        let mut watcher = Watcher::new(files);
+
+        let ret = futures_lite::future::or(
+            async { Some(vm.run_main(path, args.clone()).await) },
+            async {
+                watcher.changed(options).await;
+                None
+            },
+        )
+        .await;
+
+        if let Some(ret) = ret {
+            on_run(ret);
+            watcher.changed(options).await;
+        }
+
+        vm.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_changed_files_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("main.js");
+        std::fs::write(&path, "").unwrap();
+
+        let files = LoadedFiles::default();
+        files.insert(&path);
+        let mut watcher = Watcher::new(files.clone());
+        assert!(!watcher.poll());
+
+        // Files loaded later are recorded without counting as a change
+        let other = dir.join("other.js");
+        std::fs::write(&other, "").unwrap();
+        files.insert(&other);
+        assert!(!watcher.poll());
+
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+    }
+
+    #[test]
+    fn tracks_files_behind_assets() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        std::fs::write(dir.join("page.html"), "").unwrap();
+
+        let loader = TrackingLoader::new((), LoadedFiles::default(), dir);
+        assert_eq!(loader.file("text:page.html"), Some(dir.join("page.html")));
+        assert_eq!(loader.file("https://example.com/page.html"), None);
+    }
+}
@@ -0,0 +1,63 @@
+//! Modules the wasm loaders do not handle reach the loaders after them
+#![cfg(all(feature = "vm", feature = "wasm", feature = "http"))]
+
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+};
+
+use scriptor::VmBuilder;
+
+/// Serve `body` as javascript to every request on a local port
+fn serve(body: &'static str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\ncontent-type: application/javascript\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+
+    format!("http://{}", addr)
+}
+
+#[tokio::test]
+async fn loads_assets_and_remote_modules_past_wasm_loaders() {
+    let project = tempfile::tempdir().unwrap();
+    let dir = project.path().to_path_buf();
+    let url = serve("export const answer = 42;");
+
+    std::fs::write(dir.join("greeting.html"), "<p>hello</p>").unwrap();
+    std::fs::write(
+        dir.join("main.js"),
+        format!(
+            r#"import greeting from "text:./greeting.html";
+import {{ answer }} from "{}/answer.js";
+
+if (greeting !== "<p>hello</p>") throw new Error(`text asset: ${{greeting}}`);
+if (answer !== 42) throw new Error(`remote module: ${{answer}}`);
+"#,
+            url
+        ),
+    )
+    .unwrap();
+
+    let mut builder = VmBuilder::default();
+    builder.cwd(&dir).root(dir.join("root"));
+
+    tokio::task::LocalSet::new()
+        .run_until(async move {
+            let mut vm = builder.build().await.unwrap();
+            let code = vm.run_main(dir.join("main.js"), ()).await.unwrap();
+            assert_eq!(code, 0);
+        })
+        .await;
+}
@@ -0,0 +1,24 @@
+declare module "*.json" {
+  const value: JsonValue;
+  export default value;
+}
+
+declare module "*.txt" {
+  const text: string;
+  export default text;
+}
+
+declare module "json:*" {
+  const value: JsonValue;
+  export default value;
+}
+
+declare module "text:*" {
+  const text: string;
+  export default text;
+}
+
+declare module "bytes:*" {
+  const bytes: Uint8Array;
+  export default bytes;
+}
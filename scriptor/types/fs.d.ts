@@ -1,4 +1,21 @@
 declare module "fs" {
+  /**
+   * Operations failing with an io error reject with an `FsError`, and so do
+   * the reads and writes of a `File` and the iterators of this module. The
+   * message starts with its code, as in
+   * `ENOENT: No such file or directory, open 'x'`
+   */
+  export class FsError extends Error {
+    /** Errno style code, like `ENOENT` or `EACCES` */
+    readonly code: string;
+    /** Empty for the standard streams */
+    readonly path: string;
+    /** The failing operation, like `open` or `scandir` */
+    readonly syscall: string;
+    /** Error number of the os. Undefined when the error did not come from it */
+    readonly errno?: number;
+  }
+
   export function readFile(path: string): Promise<Uint8Array>;
 
   export function writeFile(
@@ -11,8 +28,31 @@ declare module "fs" {
     lines(): AsyncIterable<string>;
   }
 
+  interface Stat {
+    readonly size: number;
+    readonly type: "file" | "directory" | "symlink" | "other";
+    readonly isFile: boolean;
+    readonly isDirectory: boolean;
+    readonly isSymlink: boolean;
+    readonly readonly: boolean;
+    /** Permission and file type bits. Undefined on windows */
+    readonly mode?: number;
+    readonly uid?: number;
+    readonly gid?: number;
+    /** Milliseconds since the unix epoch. Undefined where not supported */
+    readonly modified?: number;
+    readonly accessed?: number;
+    readonly created?: number;
+  }
+
   class DirEntry {
     readonly path: string;
+    readonly name: string;
+    isFile(): Promise<boolean>;
+    isDir(): Promise<boolean>;
+    isSymlink(): Promise<boolean>;
+    /** Metadata of the entry itself, not following symlinks */
+    stat(): Promise<Stat>;
   }
 
   class ReadDir {
@@ -24,4 +64,109 @@ declare module "fs" {
   export function open(path: string): Promise<File>;
 
   export function readDir(path: string): Promise<ReadDir>;
+
+  interface WalkEntry {
+    readonly path: string;
+    readonly name: string;
+    /** Depth below the walked directory, starting at 1 */
+    readonly depth: number;
+    readonly type: "file" | "directory" | "symlink" | "other";
+    readonly isFile: boolean;
+    readonly isDirectory: boolean;
+    readonly isSymlink: boolean;
+  }
+
+  interface WalkOptions {
+    maxDepth?: number;
+    followSymlinks?: boolean;
+    /** Skip files ignored by `.gitignore` files */
+    gitignore?: boolean;
+    /** Leaves out the entries it returns false for. Directories are still walked */
+    filter?: (entry: WalkEntry) => boolean | Promise<boolean>;
+  }
+
+  /** Every file and directory below `path`, depth first */
+  export function walk(
+    path: string,
+    options?: WalkOptions
+  ): AsyncGenerator<WalkEntry, void>;
+
+  interface GlobOptions {
+    /** Directory relative patterns start from. Defaults to the current directory */
+    cwd?: string;
+    /** Patterns of paths to leave out. Matching directories are not walked */
+    ignore?: string | string[];
+    followSymlinks?: boolean;
+    /** Skip files ignored by `.gitignore` files */
+    gitignore?: boolean;
+  }
+
+  /**
+   * Paths matching `pattern`, like `src/**\/*.ts`, relative to `cwd`. A bad
+   * pattern rejects the first step of the iteration with an `EINVAL` error
+   */
+  export function glob(
+    pattern: string,
+    options?: GlobOptions
+  ): AsyncIterable<string>;
+
+  interface WatchEvent {
+    readonly kind: "create" | "modify" | "remove" | "rename";
+    /** Changed paths. A rename may have both the old and the new path */
+    readonly paths: string[];
+  }
+
+  interface WatchOptions {
+    /** Also watch the directories below the watched ones */
+    recursive?: boolean;
+    /**
+     * Milliseconds without changes before events are reported. Defaults to 100.
+     * Changes that keep coming are reported every ten times that
+     */
+    debounce?: number;
+  }
+
+  /**
+   * Changes to `paths`. Watching stops once the iterable is collected. When
+   * the paths can not be watched, the first step of the iteration rejects
+   */
+  export function watch(
+    paths: string | string[],
+    options?: WatchOptions
+  ): AsyncIterable<WatchEvent>;
+
+  export function stat(path: string): Promise<Stat>;
+
+  /** Like `stat`, but describes a symlink itself rather than its target */
+  export function lstat(path: string): Promise<Stat>;
+
+  export function mkdir(
+    path: string,
+    options?: { recursive?: boolean; mode?: number }
+  ): Promise<void>;
+
+  /** Remove a file or directory. `force` ignores paths that do not exist */
+  export function rm(
+    path: string,
+    options?: { recursive?: boolean; force?: boolean }
+  ): Promise<void>;
+
+  export function rename(from: string, to: string): Promise<void>;
+
+  export function copyFile(from: string, to: string): Promise<void>;
+
+  /** Copy a directory recursively. Symlinks are copied as symlinks */
+  export function copyDir(from: string, to: string): Promise<void>;
+
+  /** Create a symlink at `path` pointing to `target` */
+  export function symlink(target: string, path: string): Promise<void>;
+
+  export function readlink(path: string): Promise<string>;
+
+  export function chmod(path: string, mode: number): Promise<void>;
+
+  /** Truncate or extend a file to `len` bytes, or empty it */
+  export function truncate(path: string, len?: number): Promise<void>;
+
+  export function exists(path: string): Promise<boolean>;
 }
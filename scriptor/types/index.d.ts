@@ -5,6 +5,9 @@
 /// <reference path="fs.d.ts" />
 
 /// <reference path="global.d.ts" />
+/// <reference path="process.d.ts" />
+/// <reference path="wasm.d.ts" />
+/// <reference path="assets.d.ts" />
 
 /// <reference path="type.d.ts" />
 
@@ -0,0 +1,12 @@
+declare const process: {
+  readonly argv: string[];
+  readonly env: Record<string, string>;
+  readonly pid: number;
+  readonly platform: string;
+
+  /** Working directory of the vm, which is not necessarily that of the host process */
+  cwd(): string;
+  /** Change the working directory of the vm. The host process is not affected */
+  chdir(path: string): void;
+  exit(code?: number): never;
+};
@@ -0,0 +1,26 @@
+interface WasmMemory {
+  size(): number;
+  read(offset: number, length: number): Uint8Array;
+  write(offset: number, bytes: Uint8Array | string): void;
+}
+
+/** A function, memory or global exported by a wasm module */
+type WasmExport = ((...args: any[]) => any) | WasmMemory | number;
+
+/**
+ * Every export is also a named export, under its camel cased name.
+ * A catch-all pattern can not list them, so declare a narrower one to type them:
+ *
+ * ```ts
+ * declare module "*math.wasm" {
+ *   export const add: (a: number, b: number) => number;
+ *   export const memory: WasmMemory;
+ *   const exports: { add: typeof add; memory: WasmMemory };
+ *   export default exports;
+ * }
+ * ```
+ */
+declare module "*.wasm" {
+  const exports: Record<string, WasmExport>;
+  export default exports;
+}